@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+use opencv::Result;
+use opencv::core::{AlgorithmHint, Mat, absdiff, count_non_zero, mean, no_array};
+use opencv::imgproc::{COLOR_BGR2GRAY, cvt_color};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+//The scene counts as empty while at most max_occupancy of the mask is set and the mean
+//change between two frames is at most max_motion gray values. Once it was empty for
+//empty_seconds and the mean brightness moved by brightness_shift since the last reference,
+//the reference is refreshed
+#[derive(Deserialize, Clone, Copy)]
+pub struct AutoReferenceSettings {
+    enabled: bool,
+    max_occupancy: f64,
+    max_motion: f64,
+    empty_seconds: f64,
+    brightness_shift: f64,
+}
+
+//Refreshes the reference of the active subtractor when the light changes, e.g. while
+//daylight fades. Unlike the reset key it doesn't touch the game
+pub struct AutoReference {
+    settings: AutoReferenceSettings,
+    reference_brightness: Option<f64>,
+    prev_gray: Option<Mat>,
+    empty_since: Option<Instant>,
+}
+
+fn to_gray(img: &Mat) -> Result<Mat> {
+    if img.channels() == 1 {
+        return Ok(img.clone());
+    }
+    let mut gray = Mat::default();
+    cvt_color(
+        img,
+        &mut gray,
+        COLOR_BGR2GRAY,
+        1,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(gray)
+}
+
+fn brightness(gray: &Mat) -> Result<f64> {
+    Ok(mean(gray, &no_array())?[0])
+}
+
+impl AutoReference {
+    pub fn new(settings: AutoReferenceSettings) -> AutoReference {
+        AutoReference {
+            settings,
+            reference_brightness: None,
+            prev_gray: None,
+            empty_since: None,
+        }
+    }
+
+    //the reference was set, by hand or automatically
+    pub fn reference_set(&mut self, image: &Mat) {
+        match to_gray(image).and_then(|gray| brightness(&gray)) {
+            Ok(brightness) => self.reference_brightness = Some(brightness),
+            Err(error) => eprintln!("could not measure reference brightness {error}"),
+        }
+        self.empty_since = None;
+    }
+
+    fn is_empty(&mut self, gray: &Mat, mask: &Mat) -> Result<bool> {
+        let occupancy = count_non_zero(mask)? as f64 / mask.total().max(1) as f64;
+        let motion = match self.prev_gray.as_ref() {
+            Some(prev_gray) if prev_gray.size()? == gray.size()? => {
+                let mut difference = Mat::default();
+                absdiff(prev_gray, gray, &mut difference)?;
+                mean(&difference, &no_array())?[0]
+            }
+            _ => 0.0,
+        };
+        self.prev_gray = Some(gray.clone());
+        Ok(occupancy <= self.settings.max_occupancy && motion <= self.settings.max_motion)
+    }
+
+    //true if the reference should be refreshed with image. This counts as setting it
+    pub fn check(&mut self, image: &Mat, mask: &Mat) -> Result<bool> {
+        if !self.settings.enabled {
+            return Ok(false);
+        }
+        let gray = to_gray(image)?;
+        let current = brightness(&gray)?;
+        let reference = *self.reference_brightness.get_or_insert(current);
+        if !self.is_empty(&gray, mask)? {
+            self.empty_since = None;
+            return Ok(false);
+        }
+        let empty_for = self.empty_since.get_or_insert_with(Instant::now).elapsed();
+        if empty_for.as_secs_f64() < self.settings.empty_seconds
+            || (current - reference).abs() < self.settings.brightness_shift
+        {
+            return Ok(false);
+        }
+        println!(
+            "auto reference: scene empty for {:.0}s, brightness moved from {:.1} to {:.1}, refreshing the reference",
+            empty_for.as_secs_f64(),
+            reference,
+            current
+        );
+        self.reference_brightness = Some(current);
+        self.empty_since = None;
+        Ok(true)
+    }
+}
@@ -0,0 +1,179 @@
+use std::error::Error;
+
+use opencv::core::{CV_32F, Mat};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+//only every SAMPLE_STEP-th pixel is kept, the statistics don't need all of them
+const SAMPLE_STEP: usize = 7;
+const HISTOGRAM_BINS: usize = 256;
+
+//Noise puts the threshold at the given quantile of the empty scene scores times margin.
+//Otsu splits the scores of the empty scene and the scene with a person, so it needs
+//person_frames
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum CalibrationMethod {
+    Noise { quantile: f64, margin: f64 },
+    Otsu,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct CalibrationSettings {
+    method: CalibrationMethod,
+    empty_frames: usize,
+    person_frames: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CalibrationPhase {
+    Empty,
+    WaitingForPerson,
+    Person,
+}
+
+//Chooses the threshold of a subtractor from the scores it computes for the empty scene
+//and optionally for a scene with a person. Scores are whatever the subtractor compares
+//with its threshold, see BackgroundSubtractor::threshold_score
+pub struct Calibration {
+    settings: CalibrationSettings,
+    phase: CalibrationPhase,
+    frames: usize,
+    empty: Vec<f32>,
+    person: Vec<f32>,
+}
+
+fn sample_scores(score: &Mat) -> Result<Vec<f32>, Box<dyn Error>> {
+    let mut converted = Mat::default();
+    score.convert_to(&mut converted, CV_32F, 1.0, 0.0)?;
+    Ok(converted
+        .data_typed::<f32>()?
+        .iter()
+        .step_by(SAMPLE_STEP)
+        .copied()
+        .collect())
+}
+
+fn noise_threshold(empty: &[f32], quantile: f64, margin: f64) -> f64 {
+    let mut sorted = empty.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let idx = ((sorted.len() - 1) as f64 * quantile.clamp(0.0, 1.0)).round() as usize;
+    sorted[idx] as f64 * margin
+}
+
+//threshold that maximizes the variance between the two classes of a histogram of all scores
+fn otsu_threshold(scores: &[f32]) -> f64 {
+    let (min, max) = scores.iter().fold((f32::MAX, f32::MIN), |(min, max), s| {
+        (min.min(*s), max.max(*s))
+    });
+    let bin_width = ((max - min) as f64 / HISTOGRAM_BINS as f64).max(f64::EPSILON);
+    let mut histogram = [0_f64; HISTOGRAM_BINS];
+    for score in scores {
+        let bin = ((*score - min) as f64 / bin_width) as usize;
+        histogram[bin.min(HISTOGRAM_BINS - 1)] += 1.0;
+    }
+
+    let total = scores.len() as f64;
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, n)| i as f64 * n)
+        .sum();
+    let (mut below, mut weighted_below) = (0_f64, 0_f64);
+    let (mut best_bin, mut best_variance) = (0, 0_f64);
+    for (i, n) in histogram.iter().enumerate() {
+        below += n;
+        weighted_below += i as f64 * n;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let mean_below = weighted_below / below;
+        let mean_above = (weighted_total - weighted_below) / above;
+        let variance = below * above * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            (best_bin, best_variance) = (i, variance);
+        }
+    }
+    min as f64 + (best_bin + 1) as f64 * bin_width
+}
+
+impl Calibration {
+    pub fn new(settings: CalibrationSettings) -> Result<Calibration, Box<dyn Error>> {
+        if matches!(settings.method, CalibrationMethod::Otsu) && settings.person_frames == 0 {
+            return Err("otsu calibration needs person_frames".into());
+        }
+        println!(
+            "calibrating, keep the scene empty for {} frames",
+            settings.empty_frames
+        );
+        Ok(Calibration {
+            settings,
+            phase: CalibrationPhase::Empty,
+            frames: 0,
+            empty: Vec::new(),
+            person: Vec::new(),
+        })
+    }
+
+    //the calibration key was pressed again. Returns false if that cancels the calibration
+    pub fn advance(&mut self) -> bool {
+        if self.phase != CalibrationPhase::WaitingForPerson {
+            println!("calibration cancelled");
+            return false;
+        }
+        println!(
+            "collecting {} frames with a person",
+            self.settings.person_frames
+        );
+        self.phase = CalibrationPhase::Person;
+        true
+    }
+
+    //adds the score of a frame. Returns the threshold once all frames are collected
+    pub fn add_frame(&mut self, score: &Mat) -> Result<Option<f64>, Box<dyn Error>> {
+        let (samples, needed) = match self.phase {
+            CalibrationPhase::WaitingForPerson => return Ok(None),
+            CalibrationPhase::Empty => (&mut self.empty, self.settings.empty_frames),
+            CalibrationPhase::Person => (&mut self.person, self.settings.person_frames),
+        };
+        samples.extend(sample_scores(score)?);
+        self.frames += 1;
+        if self.frames < needed.max(1) {
+            return Ok(None);
+        }
+
+        self.frames = 0;
+        if self.phase == CalibrationPhase::Empty && self.settings.person_frames > 0 {
+            println!("step into the scene and press the calibration key again");
+            self.phase = CalibrationPhase::WaitingForPerson;
+            return Ok(None);
+        }
+        self.threshold().map(Some)
+    }
+
+    fn threshold(&self) -> Result<f64, Box<dyn Error>> {
+        if self.empty.is_empty() {
+            return Err("no scores were collected".into());
+        }
+        let threshold = match self.settings.method {
+            CalibrationMethod::Noise { quantile, margin } => {
+                noise_threshold(&self.empty, quantile, margin)
+            }
+            CalibrationMethod::Otsu => {
+                otsu_threshold(&[self.empty.as_slice(), self.person.as_slice()].concat())
+            }
+        };
+        if !self.person.is_empty() {
+            let above = self
+                .person
+                .iter()
+                .filter(|score| **score as f64 > threshold)
+                .count();
+            println!(
+                "{:.1}% of the scene with a person is above the threshold",
+                100.0 * above as f64 / self.person.len() as f64
+            );
+        }
+        Ok(threshold)
+    }
+}
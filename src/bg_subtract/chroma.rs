@@ -0,0 +1,205 @@
+use opencv::Result;
+use opencv::core::{
+    AlgorithmHint, BORDER_CONSTANT, CV_32F, Mat, MatExpr, Point, Scalar, Size, Vector, bitwise_and,
+    greater_than_mat_f64, less_than_mat_f64, max, max_mat_f64, min, no_array, split,
+};
+use opencv::imgproc::{COLOR_BGR2HSV, cvt_color, dilate, morphology_default_border_value};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::mask_filter::ellipse_kernel;
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ChromaRole {
+    Background,
+    Foreground,
+}
+
+//low and high are hsv in opencv units, hue goes from 0 to 180, saturation and value to 255.
+//A hue range with low above high wraps around, e.g. 170 to 10 for red
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct HsvRange {
+    role: ChromaRole,
+    low: [f64; 3],
+    high: [f64; 3],
+}
+
+//softness is how far in hsv units a color can be outside of a range and still partially
+//match it. Within spill_radius pixels of a silhouette, colors only count as background if
+//they are fully inside a background range, so light reflected by the backdrop doesn't eat
+//the edges. 0 disables it
+#[derive(Deserialize, Clone)]
+pub struct ChromaSettings {
+    ranges: Vec<HsvRange>,
+    softness: f64,
+    spill_radius: i32,
+}
+
+//The matte is between 0 and 1 and everything above 0.5 is a silhouette. Foreground ranges
+//always count. If there are background ranges everything not matching them counts too,
+//like with a green screen, otherwise only the foreground ranges do
+pub struct ChromaSubtractor {
+    settings: ChromaSettings,
+    matte: Option<Mat>,
+    confidence: Option<Mat>,
+}
+
+//how far every pixel is outside of low..high, 0 inside
+fn channel_distance(channel: &Mat, low: f64, high: f64, wraps: bool) -> Result<Mat> {
+    let (mut below, mut above) = (Mat::default(), Mat::default());
+    channel.convert_to(&mut below, -1, -1.0, low)?;
+    channel.convert_to(&mut above, -1, 1.0, -high)?;
+    let mut distance = Mat::default();
+    match wraps {
+        true => min(&below, &above, &mut distance)?,
+        false => max(&below, &above, &mut distance)?,
+    }
+    max_mat_f64(&distance, 0.0)?.to_mat()
+}
+
+impl ChromaSubtractor {
+    pub fn new(settings: ChromaSettings) -> ChromaSubtractor {
+        ChromaSubtractor {
+            settings,
+            matte: None,
+            confidence: None,
+        }
+    }
+
+    fn has_background(&self) -> bool {
+        self.settings
+            .ranges
+            .iter()
+            .any(|range| range.role == ChromaRole::Background)
+    }
+
+    //1 inside the range, falling off to 0 at softness outside of it
+    fn membership(&self, channels: &Vector<Mat>, range: &HsvRange) -> Result<Mat> {
+        let mut distance = channel_distance(
+            &channels.get(0)?,
+            range.low[0],
+            range.high[0],
+            range.low[0] > range.high[0],
+        )?;
+        let bounds = range.low.iter().zip(range.high.iter()).enumerate();
+        for (i, (low, high)) in bounds.skip(1) {
+            let channel = channel_distance(&channels.get(i)?, *low, *high, false)?;
+            let mut res = Mat::default();
+            max(&distance, &channel, &mut res)?;
+            distance = res;
+        }
+        //a tiny softness is a hard edge. Inside the range the distance is 0, so this is at most 1
+        let softness = self.settings.softness.max(1e-3);
+        let mut falloff = Mat::default();
+        distance.convert_to(&mut falloff, -1, -1.0 / softness, 1.0)?;
+        max_mat_f64(&falloff, 0.0)?.to_mat()
+    }
+
+    //strongest match of all ranges with the given role
+    fn matches(&self, channels: &Vector<Mat>, role: ChromaRole, size: Size) -> Result<Mat> {
+        let mut res = Mat::zeros_size(size, CV_32F)?.to_mat()?;
+        for range in self.settings.ranges.iter() {
+            if range.role != role {
+                continue;
+            }
+            let mut combined = Mat::default();
+            max(&res, &self.membership(channels, range)?, &mut combined)?;
+            res = combined;
+        }
+        Ok(res)
+    }
+
+    //around silhouettes only pixels fully inside a background range stay background
+    fn suppress_spill(&self, matte: &mut Mat, background: &Mat) -> Result<()> {
+        let silhouette = greater_than_mat_f64(matte, 0.5)?.to_mat()?;
+        let mut near = Mat::default();
+        dilate(
+            &silhouette,
+            &mut near,
+            &ellipse_kernel(self.settings.spill_radius)?,
+            Point::new(-1, -1),
+            1,
+            BORDER_CONSTANT,
+            morphology_default_border_value()?,
+        )?;
+        let partial = less_than_mat_f64(background, 1.0)?.to_mat()?;
+        let mut spill = Mat::default();
+        bitwise_and(&near, &partial, &mut spill, &no_array())?;
+        matte.set_to(&Scalar::all(1.0), &spill)?;
+        Ok(())
+    }
+}
+
+impl BackgroundSubtractor for ChromaSubtractor {
+    fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
+        let mut hsv = Mat::default();
+        cvt_color(
+            &input_img,
+            &mut hsv,
+            COLOR_BGR2HSV,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        let mut hsv_float = Mat::default();
+        hsv.convert_to(&mut hsv_float, CV_32F, 1.0, 0.0)?;
+        let mut channels: Vector<Mat> = Vector::default();
+        split(&hsv_float, &mut channels)?;
+
+        let size = hsv.size()?;
+        let foreground = self.matches(&channels, ChromaRole::Foreground, size)?;
+        let matte = match self.has_background() {
+            false => foreground,
+            true => {
+                let background = self.matches(&channels, ChromaRole::Background, size)?;
+                let mut keyed = Mat::default();
+                background.convert_to(&mut keyed, -1, -1.0, 1.0)?;
+                let mut matte = Mat::default();
+                max(&foreground, &keyed, &mut matte)?;
+                if self.settings.spill_radius > 0 {
+                    self.suppress_spill(&mut matte, &background)?;
+                }
+                matte
+            }
+        };
+        self.matte = Some(matte.clone());
+        self.confidence = Some(matte.clone());
+        greater_than_mat_f64(&matte, 0.5)
+    }
+
+    fn reset(&mut self, _background_img: Mat) {}
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: self.confidence.take(),
+            ..Default::default()
+        }
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("softness", self.settings.softness, 2.0),
+            SubtractorParameter::new("spill_radius", self.settings.spill_radius as f64, 1.0),
+        ]
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match name {
+            "softness" => self.settings.softness = value,
+            "spill_radius" => self.settings.spill_radius = value.round() as i32,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        self.matte
+            .take()
+            .map(|matte| vec![("matte".to_string(), matte)])
+            .unwrap_or_default()
+    }
+}
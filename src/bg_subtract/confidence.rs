@@ -0,0 +1,35 @@
+use opencv::Result;
+use opencv::core::{CV_32F, Mat, divide2, max_mat_f64, min_mat_f64};
+use opencv::prelude::*;
+
+//Confidence maps are CV_32F between 0 and 1. Scores are scaled so the threshold of the
+//mask ends up at 0.5, so a confidence above 0.5 means the pixel is in the mask
+
+//distance from 0.5 of pixels whose side was changed by align_with_mask
+const ALIGN_MARGIN: f64 = 0.01;
+
+pub fn clamp_unit(img: &Mat) -> Result<Mat> {
+    min_mat_f64(&max_mat_f64(img, 0.0)?.to_mat()?, 1.0)?.to_mat()
+}
+
+pub fn confidence_from_score(score: &Mat, threshold: f64) -> Result<Mat> {
+    let mut scaled = Mat::default();
+    score.convert_to(&mut scaled, CV_32F, 0.5 / threshold.max(f64::EPSILON), 0.0)?;
+    clamp_unit(&scaled)
+}
+
+//for per pixel thresholds
+pub fn confidence_from_scores(score: &Mat, threshold: &Mat) -> Result<Mat> {
+    let mut scaled = Mat::default();
+    divide2(score, threshold, &mut scaled, 0.5, CV_32F)?;
+    clamp_unit(&scaled)
+}
+
+//the mask filter and the zones change the mask after the subtractor. This moves the
+//confidence to the matching side of 0.5 again, so games can use either of them
+pub fn align_with_mask(confidence: &Mat, mask: &Mat) -> Result<Mat> {
+    let mut aligned = min_mat_f64(confidence, 0.5 - ALIGN_MARGIN)?.to_mat()?;
+    let raised = max_mat_f64(confidence, 0.5 + ALIGN_MARGIN)?.to_mat()?;
+    raised.copy_to_masked(&mut aligned, mask)?;
+    Ok(aligned)
+}
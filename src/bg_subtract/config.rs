@@ -1,8 +1,17 @@
 use serde::Deserialize;
 
+use crate::bg_subtract::auto_reference::AutoReferenceSettings;
+use crate::bg_subtract::calibration::CalibrationSettings;
+use crate::bg_subtract::chroma::ChromaSettings;
+use crate::bg_subtract::ensemble::EnsembleSettings;
+use crate::bg_subtract::mask_filter::MaskFilterSettings;
 use crate::bg_subtract::mog::MogSettings;
+use crate::bg_subtract::motion_history::MotionHistorySettings;
+use crate::bg_subtract::mouse::MouseSettings;
 use crate::bg_subtract::naive::NaiveSettings;
 use crate::bg_subtract::of::OfSettings;
+use crate::bg_subtract::scripted::ScriptedSettings;
+use crate::bg_subtract::shadow::ShadowSettings;
 use crate::bg_subtract::test::TestSettings;
 
 #[derive(Deserialize, Clone)]
@@ -11,4 +20,14 @@ pub struct BGSubtracSettings {
     pub naive_settings: NaiveSettings,
     pub of_settings: OfSettings,
     pub test_settings: TestSettings,
+    pub mouse_settings: MouseSettings,
+    pub scripted_settings: ScriptedSettings,
+    pub shadow_settings: ShadowSettings,
+    pub chroma_settings: ChromaSettings,
+    pub motion_history_settings: MotionHistorySettings,
+    pub ensemble_settings: EnsembleSettings,
+    //applied to the mask of every subtractor
+    pub mask_filter: MaskFilterSettings,
+    pub calibration: CalibrationSettings,
+    pub auto_reference: AutoReferenceSettings,
 }
@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+
+use opencv::Result;
+use opencv::core::{
+    CV_8U, CV_32F, CV_32S, Mat, MatExpr, Scalar, StsBadArg, bitwise_and, bitwise_or,
+    greater_than_mat_f64, no_array,
+};
+use opencv::imgproc::{accumulate, connected_components};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::{BGSubtracSettings, create_subtractor};
+use crate::types::{
+    BackgroundSubtractor, MaskChannels, PointerState, SubtractorParameter, SubtractorType,
+};
+
+const VOTE_EPSILON: f64 = 1e-4;
+
+//how the masks of the members are combined
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum FusionRule {
+    //every member has to see a silhouette
+    And,
+    //any member is enough
+    Or,
+    //the summed weight of the members that see a silhouette has to reach threshold times
+    //the total weight
+    Vote { threshold: f64 },
+    //silhouettes of the presence members only count once motion members saw them move.
+    //After that they are kept while they overlap the previous mask, even if they stand still
+    MotionConfirmed,
+}
+
+//motion marks the member as motion detector for MotionConfirmed, other rules ignore it
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnsembleMember {
+    subtractor: SubtractorType,
+    weight: f64,
+    #[serde(default)]
+    motion: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EnsembleSettings {
+    rule: FusionRule,
+    members: Vec<EnsembleMember>,
+}
+
+struct Member {
+    name: String,
+    settings: EnsembleMember,
+    subtractor: Box<dyn BackgroundSubtractor>,
+    mask: Option<Mat>,
+    channels: MaskChannels,
+    //set when the ensemble loaded a model but this member had none to load
+    needs_reference: bool,
+}
+
+//Runs several subtractors on the same frame and fuses their masks, so the blind spots
+//of one algorithm are covered by another. Member parameters are named member.parameter
+pub struct EnsembleSubtractor {
+    rule: FusionRule,
+    members: Vec<Member>,
+    confirmed: Option<Mat>,
+    confidence: Option<Mat>,
+}
+
+fn any_of<'a>(masks: impl Iterator<Item = &'a Mat>, size: (i32, i32)) -> Result<Mat> {
+    let mut res = Mat::zeros(size.0, size.1, CV_8U)?.to_mat()?;
+    for mask in masks {
+        let mut combined = Mat::default();
+        bitwise_or(&res, mask, &mut combined, &no_array())?;
+        res = combined;
+    }
+    Ok(res)
+}
+
+fn all_of<'a>(masks: impl Iterator<Item = &'a Mat>, size: (i32, i32)) -> Result<Mat> {
+    let mut res = Mat::new_rows_cols_with_default(size.0, size.1, CV_8U, Scalar::all(255.0))?;
+    for mask in masks {
+        let mut combined = Mat::default();
+        bitwise_and(&res, mask, &mut combined, &no_array())?;
+        res = combined;
+    }
+    Ok(res)
+}
+
+fn member_dir(dir: &str, member: &Member) -> String {
+    Path::new(dir)
+        .join("ensemble")
+        .join(&member.name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn member_masks(members: &[Member], motion: bool) -> impl Iterator<Item = &Mat> {
+    members
+        .iter()
+        .filter(move |member| member.settings.motion == motion)
+        .filter_map(|member| member.mask.as_ref())
+}
+
+//connected blobs of presence that contain at least one pixel of confirmation
+fn confirmed_blobs(presence: &Mat, confirmation: &Mat) -> Result<Mat> {
+    let mut labels = Mat::default();
+    connected_components(presence, &mut labels, 8, CV_32S)?;
+    let labels = labels.data_typed::<i32>()?;
+    let confirmed: HashSet<i32> = labels
+        .iter()
+        .zip(confirmation.data_typed::<u8>()?.iter())
+        .filter(|(label, confirms)| **label != 0 && **confirms != 0)
+        .map(|(label, _)| *label)
+        .collect();
+
+    let mut res = Mat::zeros(presence.rows(), presence.cols(), CV_8U)?.to_mat()?;
+    for (pixel, label) in res.data_typed_mut::<u8>()?.iter_mut().zip(labels.iter()) {
+        if confirmed.contains(label) {
+            *pixel = 255;
+        }
+    }
+    Ok(res)
+}
+
+impl EnsembleSubtractor {
+    pub fn new(settings: &BGSubtracSettings) -> Result<EnsembleSubtractor> {
+        let ensemble = &settings.ensemble_settings;
+        let mut members = Vec::with_capacity(ensemble.members.len());
+        for (idx, member) in ensemble.members.iter().enumerate() {
+            if member.subtractor == SubtractorType::Ensemble {
+                return Err(opencv::Error::new(
+                    StsBadArg,
+                    "an ensemble can't contain another ensemble",
+                ));
+            }
+            members.push(Member {
+                //the index keeps the names unique when a type is used twice
+                name: format!("{}_{:?}", idx, member.subtractor),
+                settings: member.clone(),
+                subtractor: create_subtractor(&member.subtractor, settings)?,
+                mask: None,
+                channels: MaskChannels::default(),
+                needs_reference: false,
+            });
+        }
+        if members.is_empty() {
+            return Err(opencv::Error::new(
+                StsBadArg,
+                "an ensemble needs at least one member",
+            ));
+        }
+        let has_motion = members.iter().any(|member| member.settings.motion);
+        let has_presence = members.iter().any(|member| !member.settings.motion);
+        if matches!(ensemble.rule, FusionRule::MotionConfirmed) && !(has_motion && has_presence) {
+            return Err(opencv::Error::new(
+                StsBadArg,
+                "MotionConfirmed needs at least one motion and one presence member",
+            ));
+        }
+        Ok(EnsembleSubtractor {
+            rule: ensemble.rule,
+            members,
+            confirmed: None,
+            confidence: None,
+        })
+    }
+
+    //weighted sum of the member masks. With soft votes members vote with their confidence
+    //if they have one
+    fn vote(&self, size: (i32, i32), soft: bool) -> Result<Mat> {
+        let mut votes = Mat::zeros(size.0, size.1, CV_32F)?.to_mat()?;
+        for member in self.members.iter() {
+            let weight = member.settings.weight;
+            let mut vote = Mat::default();
+            match (member.channels.confidence.as_ref(), member.mask.as_ref()) {
+                (Some(confidence), _) if soft => {
+                    confidence.convert_to(&mut vote, CV_32F, weight, 0.0)?
+                }
+                (_, Some(mask)) => mask.convert_to(&mut vote, CV_32F, weight / 255.0, 0.0)?,
+                _ => continue,
+            }
+            accumulate(&vote, &mut votes, &no_array())?;
+        }
+        Ok(votes)
+    }
+
+    fn motion_confirmed(&mut self, size: (i32, i32)) -> Result<Mat> {
+        let presence = any_of(member_masks(&self.members, false), size)?;
+        let motion = any_of(member_masks(&self.members, true), size)?;
+        let confirmation = match self.confirmed.as_ref() {
+            Some(confirmed) if confirmed.size()? == motion.size()? => {
+                any_of([&motion, confirmed].into_iter(), size)?
+            }
+            _ => motion,
+        };
+        let res = confirmed_blobs(&presence, &confirmation)?;
+        self.confirmed = Some(res.clone());
+        Ok(res)
+    }
+}
+
+impl BackgroundSubtractor for EnsembleSubtractor {
+    fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
+        for member in self.members.iter_mut() {
+            if member.needs_reference {
+                member.subtractor.reset(input_img.clone());
+                member.needs_reference = false;
+            }
+            let mask = member.subtractor.apply(input_img.clone())?.to_mat()?;
+            member.mask = Some(mask);
+            member.channels = member.subtractor.channels();
+        }
+        let size = (input_img.rows(), input_img.cols());
+        let total_weight: f64 = self.members.iter().map(|m| m.settings.weight).sum();
+        //the confidence is the weighted mean of the member confidences for every rule
+        let mut confidence = Mat::default();
+        self.vote(size, true)?.convert_to(
+            &mut confidence,
+            -1,
+            1.0 / total_weight.max(f64::EPSILON),
+            0.0,
+        )?;
+        self.confidence = Some(confidence);
+        let masks = self
+            .members
+            .iter()
+            .filter_map(|member| member.mask.as_ref());
+        match self.rule {
+            FusionRule::And => MatExpr::from_mat(&all_of(masks, size)?),
+            FusionRule::Or => MatExpr::from_mat(&any_of(masks, size)?),
+            //the votes are f32 sums, so reaching the threshold allows for rounding
+            FusionRule::Vote { threshold } => greater_than_mat_f64(
+                &self.vote(size, false)?,
+                threshold * total_weight - VOTE_EPSILON,
+            ),
+            FusionRule::MotionConfirmed => MatExpr::from_mat(&self.motion_confirmed(size)?),
+        }
+    }
+
+    fn reset(&mut self, background_img: Mat) {
+        for member in self.members.iter_mut() {
+            member.needs_reference = false;
+            member.subtractor.reset(background_img.clone());
+        }
+        self.confirmed = None;
+    }
+
+    //a reset resets every member, so all of them have to take the reference
+    fn takes_reference(&self) -> bool {
+        self.members
+            .iter()
+            .all(|member| member.subtractor.takes_reference())
+    }
+
+    //every member gets its own directory, members of the same type would overwrite each
+    //other otherwise
+    fn save_model(&self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        let mut saved = false;
+        for member in self.members.iter() {
+            let member_dir = member_dir(dir, member);
+            std::fs::create_dir_all(&member_dir)?;
+            saved |= member.subtractor.save_model(&member_dir)?;
+        }
+        Ok(saved)
+    }
+
+    //the pipeline only takes a reference if nothing was loaded, so members without a
+    //saved model take the next image as reference themselves
+    fn load_model(&mut self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        let mut loaded = Vec::with_capacity(self.members.len());
+        for member in self.members.iter_mut() {
+            let member_dir = member_dir(dir, member);
+            loaded.push(member.subtractor.load_model(&member_dir)?);
+        }
+        let any_loaded = loaded.contains(&true);
+        for (member, loaded) in self.members.iter_mut().zip(loaded) {
+            member.needs_reference = any_loaded && !loaded;
+        }
+        Ok(any_loaded)
+    }
+
+    //the first member with a motion age provides it
+    fn channels(&mut self) -> MaskChannels {
+        let motion_age = self
+            .members
+            .iter_mut()
+            .find_map(|member| member.channels.motion_age.take());
+        MaskChannels {
+            motion_age,
+            confidence: self.confidence.take(),
+        }
+    }
+
+    fn pointer_event(&mut self, pointer: PointerState) {
+        for member in self.members.iter_mut() {
+            member.subtractor.pointer_event(pointer);
+        }
+    }
+
+    fn rendered_frame(&mut self, frame: Mat) {
+        for member in self.members.iter_mut() {
+            member.subtractor.rendered_frame(frame.clone());
+        }
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        let mut parameters = match self.rule {
+            FusionRule::Vote { threshold } => {
+                vec![SubtractorParameter::new("vote_threshold", threshold, 0.05)]
+            }
+            _ => Vec::new(),
+        };
+        for member in self.members.iter() {
+            parameters.push(SubtractorParameter::new(
+                &format!("{}.weight", member.name),
+                member.settings.weight,
+                0.1,
+            ));
+            for parameter in member.subtractor.parameters() {
+                parameters.push(SubtractorParameter::new(
+                    &format!("{}.{}", member.name, parameter.name),
+                    parameter.value,
+                    parameter.step,
+                ));
+            }
+        }
+        parameters
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        if let ("vote_threshold", FusionRule::Vote { threshold }) = (name, &mut self.rule) {
+            *threshold = value.min(1.0);
+            return Ok(());
+        }
+        let (member_name, parameter) = name
+            .split_once('.')
+            .ok_or_else(|| format!("unknown parameter {}", name))?;
+        let member = self
+            .members
+            .iter_mut()
+            .find(|member| member.name == member_name)
+            .ok_or_else(|| format!("unknown ensemble member {}", member_name))?;
+        match parameter {
+            "weight" => member.settings.weight = value,
+            _ => member.subtractor.set_parameter(parameter, value)?,
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        let mut stages = Vec::new();
+        for member in self.members.iter_mut() {
+            if let Some(mask) = member.mask.take() {
+                stages.push((format!("{} mask", member.name), mask));
+            }
+            for (name, stage) in member.subtractor.stages() {
+                stages.push((format!("{} {}", member.name, name), stage));
+            }
+        }
+        stages
+    }
+}
@@ -0,0 +1,55 @@
+use opencv::Result;
+use opencv::core::{BORDER_CONSTANT, Mat, Point, Size};
+use opencv::imgproc::{
+    MORPH_CLOSE, MORPH_ELLIPSE, MORPH_OPEN, get_structuring_element, median_blur,
+    morphology_default_border_value, morphology_ex,
+};
+use serde::Deserialize;
+
+//Cleanup of the mask of every subtractor. Opening removes speckles smaller than
+//open_radius, closing fills holes smaller than close_radius and the median smooths ragged
+//edges. Radii are in pixels, 0 disables a step
+#[derive(Deserialize, Clone, Copy)]
+pub struct MaskFilterSettings {
+    open_radius: i32,
+    close_radius: i32,
+    median_size: i32,
+}
+
+pub fn ellipse_kernel(radius: i32) -> Result<Mat> {
+    get_structuring_element(
+        MORPH_ELLIPSE,
+        Size::new(2 * radius + 1, 2 * radius + 1),
+        Point::new(-1, -1),
+    )
+}
+
+fn morphology(mask: Mat, operation: i32, radius: i32) -> Result<Mat> {
+    if radius <= 0 {
+        return Ok(mask);
+    }
+    let mut res = Mat::default();
+    morphology_ex(
+        &mask,
+        &mut res,
+        operation,
+        &ellipse_kernel(radius)?,
+        Point::new(-1, -1),
+        1,
+        BORDER_CONSTANT,
+        morphology_default_border_value()?,
+    )?;
+    Ok(res)
+}
+
+pub fn filter_mask(mask: Mat, settings: &MaskFilterSettings) -> Result<Mat> {
+    let opened = morphology(mask, MORPH_OPEN, settings.open_radius)?;
+    let closed = morphology(opened, MORPH_CLOSE, settings.close_radius)?;
+    if settings.median_size <= 1 {
+        return Ok(closed);
+    }
+    //median blur only takes odd sizes
+    let mut res = Mat::default();
+    median_blur(&closed, &mut res, settings.median_size | 1)?;
+    Ok(res)
+}
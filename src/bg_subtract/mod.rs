@@ -1,11 +1,63 @@
+mod auto_reference;
+mod calibration;
+mod chroma;
+mod confidence;
 mod config;
+mod ensemble;
+mod mask_filter;
 mod mog;
+mod motion_history;
+mod mouse;
 mod naive;
 mod of;
+mod scripted;
+mod shadow;
+mod shapes;
 mod test;
+mod zones;
 
+pub use auto_reference::AutoReference;
+pub use calibration::Calibration;
+pub use chroma::ChromaSubtractor;
+pub use confidence::align_with_mask;
 pub use config::BGSubtracSettings;
+pub use ensemble::EnsembleSubtractor;
+pub use mask_filter::filter_mask;
 pub use mog::MogSubtractor;
+pub use motion_history::MotionHistorySubtractor;
+pub use mouse::MouseSubtractor;
 pub use naive::NaiveSubtractor;
 pub use of::OfSubtractor;
+pub use scripted::{Script, ScriptedSubtractor, load_script};
+pub use shadow::ShadowSubtractor;
 pub use test::TestSubtractor;
+pub use zones::{ZoneMask, Zones, load_zones, save_zones};
+
+use crate::types::{BackgroundSubtractor, SubtractorType};
+
+pub fn create_subtractor(
+    selected_type: &SubtractorType,
+    settings: &BGSubtracSettings,
+) -> opencv::Result<Box<dyn BackgroundSubtractor>> {
+    let settings = settings.clone();
+    Ok(match selected_type {
+        SubtractorType::Mog => {
+            Box::new(MogSubtractor::new(settings.mog_settings)?) as Box<dyn BackgroundSubtractor>
+        }
+        SubtractorType::Naive => {
+            Box::new(NaiveSubtractor::new(settings.naive_settings)) as Box<dyn BackgroundSubtractor>
+        }
+        SubtractorType::OpticalFlow => Box::new(OfSubtractor::new(settings.of_settings)?),
+        SubtractorType::Test => Box::new(TestSubtractor {
+            settings: settings.test_settings,
+        }),
+        SubtractorType::Mouse => Box::new(MouseSubtractor::new(settings.mouse_settings)),
+        SubtractorType::Scripted => Box::new(ScriptedSubtractor::new(settings.scripted_settings)?),
+        SubtractorType::Shadow => Box::new(ShadowSubtractor::new(settings.shadow_settings)),
+        SubtractorType::Chroma => Box::new(ChromaSubtractor::new(settings.chroma_settings)),
+        SubtractorType::MotionHistory => Box::new(MotionHistorySubtractor::new(
+            settings.motion_history_settings,
+        )),
+        SubtractorType::Ensemble => Box::new(EnsembleSubtractor::new(&settings)?),
+    })
+}
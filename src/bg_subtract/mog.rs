@@ -1,12 +1,23 @@
 use opencv::Result;
 use opencv::bgsegm::{BackgroundSubtractorMOG, create_background_subtractor_mog};
 use opencv::core::greater_than_mat_f64;
-use opencv::core::{Mat, MatExpr, Ptr};
+use opencv::core::{CV_32F, Mat, MatExpr, Ptr, Vector, add_weighted, no_array};
+use opencv::imgcodecs::{ImreadModes, imread, imwrite};
+use opencv::imgproc::accumulate_weighted;
 use opencv::prelude::*;
 
 use serde::Deserialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::Path;
 
-use crate::types::BackgroundSubtractor;
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
+
+//mog only gives a binary mask, so the confidence also uses how often a pixel was
+//foreground recently. This is the weight of the newest frame in that rate
+const CONFIDENCE_RATE: f64 = 0.5;
+//subdirectory of the model directory with the retraining frames
+const MODEL_DIR: &str = "mog";
 
 #[derive(Deserialize, Clone, Copy)]
 pub struct MogSettings {
@@ -15,11 +26,20 @@ pub struct MogSettings {
     background_ratio: f64,
     noise_sigma: f64,
     learning_rate: f64,
+    //frames kept to retrain the model after a restart, spread over the history
+    retrain_frames: usize,
 }
 
 pub struct MogSubtractor {
     subtractor: Ptr<BackgroundSubtractorMOG>,
     settings: MogSettings,
+    raw_mask: Option<Mat>,
+    foreground_rate: Mat,
+    confidence: Option<Mat>,
+    //opencv can't export the mixtures, so the model is saved as recent input frames
+    //and retrained from them
+    retrain: VecDeque<Mat>,
+    frames_seen: usize,
 }
 
 fn mog_from_settings(
@@ -29,6 +49,7 @@ fn mog_from_settings(
         background_ratio,
         noise_sigma,
         learning_rate: _,
+        retrain_frames: _,
     }: MogSettings,
 ) -> Result<Ptr<BackgroundSubtractorMOG>> {
     create_background_subtractor_mog(history, mixtures, background_ratio, noise_sigma)
@@ -40,8 +61,44 @@ impl MogSubtractor {
         Ok(MogSubtractor {
             subtractor,
             settings,
+            raw_mask: None,
+            foreground_rate: Mat::default(),
+            confidence: None,
+            retrain: VecDeque::new(),
+            frames_seen: 0,
         })
     }
+
+    //frames of a model loaded at another resolution are dropped, mog starts over with a
+    //new size anyway
+    fn remember_frame(&mut self, frame: &Mat) -> Result<()> {
+        let resized = match self.retrain.front() {
+            Some(front) => front.size()? != frame.size()?,
+            None => false,
+        };
+        if resized {
+            self.retrain.clear();
+        }
+        let wanted = self.settings.retrain_frames;
+        let interval = (self.settings.history.max(1) as usize / wanted.max(1)).max(1);
+        if wanted > 0 && self.frames_seen % interval == 0 {
+            if self.retrain.len() >= wanted {
+                self.retrain.pop_front();
+            }
+            self.retrain.push_back(frame.clone());
+        }
+        self.frames_seen += 1;
+        Ok(())
+    }
+}
+
+fn retrain_paths(dir: &str) -> std::result::Result<Vec<std::path::PathBuf>, Box<dyn Error>> {
+    let mut paths = std::fs::read_dir(Path::new(dir).join(MODEL_DIR))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "png"));
+    paths.sort();
+    Ok(paths)
 }
 
 impl BackgroundSubtractor for MogSubtractor {
@@ -51,14 +108,144 @@ impl BackgroundSubtractor for MogSubtractor {
             .subtractor
             .apply(&input_img, &mut mask, self.settings.learning_rate);
         res?;
-        greater_than_mat_f64(&mask, 0.5)
+        self.remember_frame(&input_img)?;
+        let result = greater_than_mat_f64(&mask, 0.5);
+        let mut foreground = Mat::default();
+        mask.convert_to(&mut foreground, CV_32F, 1.0 / 255.0, 0.0)?;
+        match self.foreground_rate.size()? == foreground.size()? {
+            true => accumulate_weighted(
+                &foreground,
+                &mut self.foreground_rate,
+                CONFIDENCE_RATE,
+                &no_array(),
+            )?,
+            false => self.foreground_rate = foreground.clone(),
+        }
+        //the current mask decides the side of 0.5, the rate how far from it. A pixel in the
+        //mask has a rate above 0, one outside a rate below 1
+        let mut confidence = Mat::default();
+        add_weighted(
+            &self.foreground_rate,
+            0.5,
+            &foreground,
+            0.5,
+            0.0,
+            &mut confidence,
+            -1,
+        )?;
+        self.confidence = Some(confidence);
+        self.raw_mask = Some(mask);
+        result
     }
 
     fn reset(&mut self, _background_img: Mat) {
+        self.foreground_rate = Mat::default();
+        self.retrain.clear();
+        self.frames_seen = 0;
         if let Ok(subtractor) = mog_from_settings(self.settings) {
             self.subtractor = subtractor;
         } else {
             eprint!("could not reset mog background subtractor");
         }
     }
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: self.confidence.take(),
+            ..Default::default()
+        }
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("learning_rate", self.settings.learning_rate, 0.01),
+            SubtractorParameter::new("history", self.settings.history as f64, 10.0),
+            SubtractorParameter::new("mixtures", self.settings.mixtures as f64, 1.0),
+            SubtractorParameter::new("background_ratio", self.settings.background_ratio, 0.05),
+            SubtractorParameter::new("noise_sigma", self.settings.noise_sigma, 0.5),
+        ]
+    }
+
+    //everything but the learning rate is part of the model, so the model is recreated.
+    //The settings only change if that works
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut settings = self.settings;
+        //mog needs at least one frame of history and one mixture
+        let count = value.round().max(1.0) as i32;
+        match name {
+            "learning_rate" => {
+                self.settings.learning_rate = value;
+                return Ok(());
+            }
+            "history" => settings.history = count,
+            "mixtures" => settings.mixtures = count,
+            "background_ratio" => settings.background_ratio = value,
+            "noise_sigma" => settings.noise_sigma = value,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        self.subtractor = mog_from_settings(settings)?;
+        self.settings = settings;
+        Ok(())
+    }
+
+    //old frames are removed first, so a save never mixes two models
+    fn save_model(&self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        if self.retrain.is_empty() {
+            return Err("no frames to retrain from yet".into());
+        }
+        std::fs::create_dir_all(Path::new(dir).join(MODEL_DIR))?;
+        for path in retrain_paths(dir)? {
+            std::fs::remove_file(path)?;
+        }
+        for (i, frame) in self.retrain.iter().enumerate() {
+            let path = Path::new(dir)
+                .join(MODEL_DIR)
+                .join(format!("frame_{:04}.png", i));
+            if !imwrite(&path.to_string_lossy(), frame, &Vector::new())? {
+                return Err(format!("could not write {}", path.display()).into());
+            }
+        }
+        Ok(true)
+    }
+
+    //the frames are replayed with opencv's automatic learning rate, which weighs them
+    //like a model that saw nothing else
+    fn load_model(&mut self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        if !Path::new(dir).join(MODEL_DIR).exists() {
+            return Ok(false);
+        }
+        let paths = retrain_paths(dir)?;
+        if paths.is_empty() {
+            return Ok(false);
+        }
+        let mut subtractor = mog_from_settings(self.settings)?;
+        let mut mask = Mat::default();
+        self.retrain.clear();
+        for path in paths.iter() {
+            let frame = imread(
+                &path.to_string_lossy(),
+                ImreadModes::IMREAD_COLOR_BGR.into(),
+            )?;
+            if frame.empty() {
+                return Err(format!("could not read {}", path.display()).into());
+            }
+            subtractor.apply(&frame, &mut mask, -1.0)?;
+            self.retrain.push_back(frame);
+        }
+        self.subtractor = subtractor;
+        self.foreground_rate = Mat::default();
+        println!("retrained mog from {} frames", paths.len());
+        Ok(true)
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        self.raw_mask
+            .take()
+            .map(|raw_mask| vec![("raw mask".to_string(), raw_mask)])
+            .unwrap_or_default()
+    }
 }
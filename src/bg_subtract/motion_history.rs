@@ -0,0 +1,164 @@
+use std::time::Instant;
+
+use opencv::Result;
+use opencv::core::{
+    AlgorithmHint, BORDER_DEFAULT, CV_32F, Mat, MatExpr, Scalar, Size, absdiff,
+    greater_than_mat_f64, less_than_mat_f64, min_mat_f64,
+};
+use opencv::imgproc::{COLOR_BGR2GRAY, cvt_color, gaussian_blur};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::confidence::clamp_unit;
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
+
+//diff_threshold is the change in gray value between two frames that counts as motion.
+//Pixels that moved within the last mask_duration seconds are in the mask, the age of
+//motion is tracked up to history seconds
+#[derive(Deserialize, Clone, Copy)]
+pub struct MotionHistorySettings {
+    diff_threshold: f64,
+    blur: f64,
+    mask_duration: f64,
+    history: f64,
+}
+
+//Keeps the seconds since the last motion of every pixel. Much cheaper than dense optical
+//flow, and unlike it remembers where something moved
+pub struct MotionHistorySubtractor {
+    settings: MotionHistorySettings,
+    prev_img: Option<Mat>,
+    age: Mat,
+    last_update: Instant,
+    difference: Option<Mat>,
+}
+
+impl MotionHistorySubtractor {
+    pub fn new(settings: MotionHistorySettings) -> MotionHistorySubtractor {
+        MotionHistorySubtractor {
+            settings,
+            prev_img: None,
+            age: Mat::default(),
+            last_update: Instant::now(),
+            difference: None,
+        }
+    }
+
+    fn gray(&self, img: &Mat) -> Result<Mat> {
+        let mut gray = Mat::default();
+        cvt_color(
+            img,
+            &mut gray,
+            COLOR_BGR2GRAY,
+            1,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        if self.settings.blur <= 0.0 {
+            return Ok(gray);
+        }
+        let mut blurred = Mat::default();
+        gaussian_blur(
+            &gray,
+            &mut blurred,
+            Size::new(0, 0),
+            self.settings.blur,
+            self.settings.blur,
+            BORDER_DEFAULT,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        Ok(blurred)
+    }
+}
+
+impl BackgroundSubtractor for MotionHistorySubtractor {
+    fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
+        let gray = self.gray(&input_img)?;
+        let dt = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+        //nothing moved before the first frame
+        if self.age.size()? != gray.size()? {
+            self.age = Mat::new_rows_cols_with_default(
+                gray.rows(),
+                gray.cols(),
+                CV_32F,
+                Scalar::all(self.settings.history),
+            )?;
+        }
+
+        let mut aged = Mat::default();
+        self.age.convert_to(&mut aged, -1, 1.0, dt)?;
+        self.age = min_mat_f64(&aged, self.settings.history)?.to_mat()?;
+        if let Some(prev_img) = self.prev_img.as_ref() {
+            let mut difference = Mat::default();
+            absdiff(prev_img, &gray, &mut difference)?;
+            let motion = greater_than_mat_f64(&difference, self.settings.diff_threshold)?;
+            self.age.set_to(&Scalar::all(0.0), &motion)?;
+            self.difference = Some(difference);
+        }
+        self.prev_img = Some(gray);
+        less_than_mat_f64(&self.age, self.settings.mask_duration)
+    }
+
+    fn reset(&mut self, _background_img: Mat) {
+        self.prev_img = None;
+        self.age = Mat::default();
+    }
+
+    //confidence fades from 1 at fresh motion to 0.5 at mask_duration
+    fn channels(&mut self) -> MaskChannels {
+        let mut faded = Mat::default();
+        let confidence = self
+            .age
+            .convert_to(
+                &mut faded,
+                -1,
+                -0.5 / self.settings.mask_duration.max(f64::EPSILON),
+                1.0,
+            )
+            .and_then(|_| clamp_unit(&faded));
+        MaskChannels {
+            motion_age: Some(self.age.clone()),
+            confidence: confidence.ok(),
+        }
+    }
+
+    fn calibration_target(&self) -> Option<(&'static str, &'static str)> {
+        Some(("motion_history_settings", "diff_threshold"))
+    }
+
+    fn threshold_score(&self) -> Option<Mat> {
+        self.difference.clone()
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("diff_threshold", self.settings.diff_threshold, 2.0),
+            SubtractorParameter::new("blur", self.settings.blur, 0.5),
+            SubtractorParameter::new("mask_duration", self.settings.mask_duration, 0.1),
+            SubtractorParameter::new("history", self.settings.history, 0.5),
+        ]
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match name {
+            "diff_threshold" => self.settings.diff_threshold = value,
+            "blur" => self.settings.blur = value,
+            "mask_duration" => self.settings.mask_duration = value,
+            "history" => self.settings.history = value,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        let mut stages = vec![("motion age".to_string(), self.age.clone())];
+        if let Some(difference) = self.difference.take() {
+            stages.push(("difference".to_string(), difference));
+        }
+        stages
+    }
+}
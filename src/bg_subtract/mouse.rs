@@ -0,0 +1,161 @@
+use std::time::Instant;
+
+use opencv::Result;
+use opencv::core::{
+    AlgorithmHint, BORDER_DEFAULT, CV_32F, Mat, MatExpr, Point, Scalar, Size, greater_than_mat_f64,
+    max,
+};
+use opencv::imgproc::{LINE_8, gaussian_blur, line};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::shapes::draw_person;
+use crate::types::{BackgroundSubtractor, MaskChannels, PointerState, SubtractorParameter};
+
+//image_size is the size of the blank camera images used instead of a camera.
+//brush_radius and stamp_height are relative to the image height, decay_per_second is
+//the fraction of the painted silhouette that is left after one second
+#[derive(Deserialize, Clone, Copy)]
+pub struct MouseSettings {
+    pub image_size: (i32, i32),
+    brush_radius: f64,
+    decay_per_second: f64,
+    stamp_height: f64,
+}
+
+//Builds the mask from the mouse instead of the camera. Dragging paints blobs, in stamp
+//mode a person is stamped at the cursor. Painted silhouettes fade out over time
+pub struct MouseSubtractor {
+    settings: MouseSettings,
+    pointer: PointerState,
+    last_painted: Option<Point>,
+    canvas: Mat,
+    last_update: Instant,
+}
+
+impl MouseSubtractor {
+    pub fn new(settings: MouseSettings) -> MouseSubtractor {
+        MouseSubtractor {
+            settings,
+            pointer: PointerState::default(),
+            last_painted: None,
+            canvas: Mat::default(),
+            last_update: Instant::now(),
+        }
+    }
+
+    //the mask is mirrored on both axes compared to opengl coordinates
+    fn pointer_to_pixel(&self) -> Point {
+        let (x, y) = self.pointer.position;
+        Point::new(
+            ((1_f32 - x) / 2_f32 * self.canvas.cols() as f32) as i32,
+            ((1_f32 - y) / 2_f32 * self.canvas.rows() as f32) as i32,
+        )
+    }
+
+    //paints the current pointer into the canvas. The stroke is blurred, so the painted
+    //silhouettes shrink smoothly while they fade
+    fn paint(&mut self) -> Result<()> {
+        if !self.pointer.pressed {
+            self.last_painted = None;
+            return Ok(());
+        }
+        let position = self.pointer_to_pixel();
+        let radius = (self.settings.brush_radius * self.canvas.rows() as f64).max(1.0);
+        let mut stroke = Mat::zeros(self.canvas.rows(), self.canvas.cols(), CV_32F)?.to_mat()?;
+        match self.pointer.stamp {
+            true => draw_person(
+                &mut stroke,
+                position,
+                self.settings.stamp_height * self.canvas.rows() as f64,
+                Scalar::all(1.0),
+            )?,
+            false => line(
+                &mut stroke,
+                self.last_painted.unwrap_or(position),
+                position,
+                Scalar::all(1.0),
+                (2.0 * radius) as i32,
+                LINE_8,
+                0,
+            )?,
+        }
+        self.last_painted = Some(position);
+
+        let mut blurred = Mat::default();
+        gaussian_blur(
+            &stroke,
+            &mut blurred,
+            Size::new(0, 0),
+            radius / 3.0,
+            radius / 3.0,
+            BORDER_DEFAULT,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        let mut painted = Mat::default();
+        max(&self.canvas, &blurred, &mut painted)?;
+        self.canvas = painted;
+        Ok(())
+    }
+}
+
+impl BackgroundSubtractor for MouseSubtractor {
+    fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
+        if self.canvas.rows() != input_img.rows() || self.canvas.cols() != input_img.cols() {
+            self.canvas = Mat::zeros(input_img.rows(), input_img.cols(), CV_32F)?.to_mat()?;
+        }
+        let dt = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+
+        let mut faded = Mat::default();
+        let decay = self.settings.decay_per_second.powf(dt);
+        self.canvas.convert_to(&mut faded, -1, decay, 0.0)?;
+        self.canvas = faded;
+
+        self.paint()?;
+        greater_than_mat_f64(&self.canvas, 0.5)
+    }
+
+    fn reset(&mut self, _background_img: Mat) {
+        self.canvas = Mat::default();
+        self.last_painted = None;
+    }
+
+    //the canvas already fades smoothly
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: Some(self.canvas.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn pointer_event(&mut self, pointer: PointerState) {
+        self.pointer = pointer;
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("brush_radius", self.settings.brush_radius, 0.01),
+            SubtractorParameter::new("decay_per_second", self.settings.decay_per_second, 0.05),
+            SubtractorParameter::new("stamp_height", self.settings.stamp_height, 0.05),
+        ]
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match name {
+            "brush_radius" => self.settings.brush_radius = value,
+            "decay_per_second" => self.settings.decay_per_second = value.min(1.0),
+            "stamp_height" => self.settings.stamp_height = value,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        vec![("canvas".to_string(), self.canvas.clone())]
+    }
+}
@@ -1,46 +1,338 @@
-use crate::types::BackgroundSubtractor;
+use crate::bg_subtract::confidence::{confidence_from_score, confidence_from_scores};
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
 use opencv::Result;
-use opencv::core::{Mat, MatExpr, Vector, absdiff, greater_than_mat_f64, split};
+use opencv::core::{
+    CV_8U, CV_32F, FileStorage, FileStorage_Mode, Mat, MatExpr, Vector, absdiff, bitwise_not,
+    greater_than_mat_f64, greater_than_mat_mat, max_mat_f64, no_array, split, sqrt, subtract,
+};
+use opencv::imgproc::{accumulate, accumulate_square, accumulate_weighted};
 use opencv::prelude::*;
 use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+
+//the float reference and noise model don't fit into an image file, opencv's own format
+//keeps them as they are
+const MODEL_FILE: &str = "naive.yml.gz";
+
+//how the reference frames are combined into the background
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum ReferenceMode {
+    Mean,
+    Median,
+}
+
+//the threshold of every pixel is noise_factor times the standard deviation of that pixel
+//in the reference frames, but at least min_threshold
+#[derive(Deserialize, Clone, Copy)]
+pub struct AdaptiveThreshold {
+    noise_factor: f64,
+    min_threshold: f64,
+}
 
 #[derive(Deserialize, Clone)]
 pub struct NaiveSettings {
     threshold_per_channel: f64,
+    reference_frames: usize,
+    reference_mode: ReferenceMode,
+    learning_rate: f64,
+    adaptive_threshold: Option<AdaptiveThreshold>,
 }
 
+//After a reset the next reference_frames images are collected and combined into the
+//background. The mask stays empty until then
 pub struct NaiveSubtractor {
     pub background_approximation: Mat,
     pub settings: NaiveSettings,
+    reference_frames: Vec<Mat>,
+    noise: Option<Mat>,
+    difference: Option<Mat>,
+    confidence: Option<Mat>,
 }
 
-fn naive_background_removal(img: Mat, ref_img: Mat, threshold: f64) -> Result<MatExpr> {
+impl NaiveSubtractor {
+    pub fn new(settings: NaiveSettings) -> NaiveSubtractor {
+        NaiveSubtractor {
+            background_approximation: Mat::default(),
+            settings,
+            reference_frames: Vec::new(),
+            noise: None,
+            difference: None,
+            confidence: None,
+        }
+    }
+
+    fn is_collecting(&self) -> bool {
+        !self.reference_frames.is_empty()
+    }
+
+    fn add_reference_frame(&mut self, frame: Mat) -> Result<()> {
+        self.reference_frames.push(frame);
+        if self.reference_frames.len() < self.settings.reference_frames.max(1) {
+            return Ok(());
+        }
+        let frames = std::mem::take(&mut self.reference_frames);
+        self.background_approximation = match self.settings.reference_mode {
+            ReferenceMode::Mean => mean_reference(&frames)?,
+            ReferenceMode::Median => median_reference(&frames)?,
+        };
+        self.noise = Some(noise_model(&frames, &self.background_approximation)?);
+        println!("naive reference built from {} frames", frames.len());
+        Ok(())
+    }
+
+    fn threshold(&self) -> Result<Option<Mat>> {
+        match (&self.noise, self.settings.adaptive_threshold) {
+            (Some(noise), Some(adaptive)) => {
+                let scaled = (noise * adaptive.noise_factor).into_result()?.to_mat()?;
+                Ok(Some(
+                    max_mat_f64(&scaled, adaptive.min_threshold)?.to_mat()?,
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    //background pixels slowly follow the input, silhouettes don't leak into the background
+    fn update_background(&mut self, input: &Mat, mask: &Mat) -> Result<()> {
+        if self.settings.learning_rate <= 0.0 {
+            return Ok(());
+        }
+        let mut background = Mat::default();
+        bitwise_not(mask, &mut background, &no_array())?;
+        accumulate_weighted(
+            input,
+            &mut self.background_approximation,
+            self.settings.learning_rate,
+            &background,
+        )
+    }
+}
+
+fn to_float(img: &Mat) -> Result<Mat> {
     let mut res = Mat::default();
-    let _ = absdiff(&img, &ref_img, &mut res);
+    img.convert_to(&mut res, CV_32F, 1.0, 0.0)?;
+    Ok(res)
+}
 
+fn sum_channels(img: &Mat) -> Result<Mat> {
     let mut channels: Vector<Mat> = Vector::default();
-    let _ = split(&res, &mut channels);
+    split(img, &mut channels)?;
 
     let init = channels.get(0);
-    let acc = channels
+    channels
         .iter()
         .skip(1)
-        .fold(init, |acc, m| (acc? + (m)).into_result()?.to_mat());
-    let acc_res = acc?;
+        .fold(init, |acc, m| (acc? + (m)).into_result()?.to_mat())
+}
+
+fn mean_reference(frames: &[Mat]) -> Result<Mat> {
+    let mut sum = Mat::zeros_size(frames[0].size()?, frames[0].typ())?.to_mat()?;
+    for frame in frames {
+        accumulate(frame, &mut sum, &no_array())?;
+    }
+    let mut res = Mat::default();
+    sum.convert_to(&mut res, -1, 1.0 / frames.len() as f64, 0.0)?;
+    Ok(res)
+}
+
+//per pixel and channel median. The frames are viewed as single channel images, so every
+//value can be handled the same way
+fn median_reference(frames: &[Mat]) -> Result<Mat> {
+    let channels = frames[0].channels();
+    let flat_frames = frames
+        .iter()
+        .map(|frame| frame.reshape(1, 0)?.try_clone())
+        .collect::<Result<Vec<Mat>>>()?;
+    let data = flat_frames
+        .iter()
+        .map(|frame| frame.data_typed::<f32>())
+        .collect::<Result<Vec<&[f32]>>>()?;
 
-    greater_than_mat_f64(&acc_res, threshold)
+    let mut median = flat_frames[0].clone();
+    let mut values = vec![0_f32; frames.len()];
+    for (i, pixel) in median.data_typed_mut::<f32>()?.iter_mut().enumerate() {
+        for (value, frame) in values.iter_mut().zip(data.iter()) {
+            *value = frame[i];
+        }
+        values.sort_by(f32::total_cmp);
+        *pixel = values[values.len() / 2];
+    }
+    median.reshape(channels, 0)?.try_clone()
+}
+
+//per pixel standard deviation of the reference frames. It is summed over the channels
+//like the difference, so both can be compared directly
+fn noise_model(frames: &[Mat], reference: &Mat) -> Result<Mat> {
+    let mut squared = Mat::zeros_size(reference.size()?, reference.typ())?.to_mat()?;
+    for frame in frames {
+        let mut deviation = Mat::default();
+        subtract(frame, reference, &mut deviation, &no_array(), -1)?;
+        accumulate_square(&deviation, &mut squared, &no_array())?;
+    }
+    let mut variance = Mat::default();
+    squared.convert_to(&mut variance, -1, 1.0 / frames.len() as f64, 0.0)?;
+    let mut deviation = Mat::default();
+    sqrt(&variance, &mut deviation)?;
+    sum_channels(&deviation)
 }
 
 impl BackgroundSubtractor for NaiveSubtractor {
     fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
-        naive_background_removal(
-            input_img,
-            self.background_approximation.clone(),
-            self.settings.threshold_per_channel,
-        )
+        let input = to_float(&input_img)?;
+        //a loaded model may come from another camera resolution, then it is rebuilt
+        let outdated =
+            !self.is_collecting() && self.background_approximation.size()? != input.size()?;
+        if outdated {
+            println!("the naive reference doesn't match the camera image, building a new one");
+            self.noise = None;
+        }
+        if self.is_collecting() || outdated {
+            self.add_reference_frame(input)?;
+            return Mat::zeros(input_img.rows(), input_img.cols(), CV_8U);
+        }
+
+        let mut channel_difference = Mat::default();
+        absdiff(
+            &input,
+            &self.background_approximation,
+            &mut channel_difference,
+        )?;
+        let difference = sum_channels(&channel_difference)?;
+        let (mask, confidence) = match self.threshold()? {
+            Some(threshold) => (
+                greater_than_mat_mat(&difference, &threshold)?,
+                confidence_from_scores(&difference, &threshold)?,
+            ),
+            None => (
+                greater_than_mat_f64(&difference, self.settings.threshold_per_channel)?,
+                confidence_from_score(&difference, self.settings.threshold_per_channel)?,
+            ),
+        };
+        self.update_background(&input, &mask.to_mat()?)?;
+        self.difference = Some(difference);
+        self.confidence = Some(confidence);
+        Ok(mask)
     }
 
     fn reset(&mut self, background_img: Mat) {
-        self.background_approximation = background_img;
+        self.reference_frames.clear();
+        let res = to_float(&background_img).and_then(|frame| self.add_reference_frame(frame));
+        if let Err(error) = res {
+            eprintln!("could not set naive reference {error}");
+        }
+    }
+
+    fn takes_reference(&self) -> bool {
+        true
+    }
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: self.confidence.take(),
+            ..Default::default()
+        }
+    }
+
+    //with an adaptive threshold threshold_per_channel isn't used, and the per pixel
+    //threshold can't be calibrated from a single value
+    fn calibration_target(&self) -> Option<(&'static str, &'static str)> {
+        self.settings
+            .adaptive_threshold
+            .is_none()
+            .then_some(("naive_settings", "threshold_per_channel"))
+    }
+
+    fn threshold_score(&self) -> Option<Mat> {
+        match self.is_collecting() {
+            true => None,
+            false => self.difference.clone(),
+        }
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        let mut parameters = vec![
+            SubtractorParameter::new(
+                "threshold_per_channel",
+                self.settings.threshold_per_channel,
+                5.0,
+            ),
+            SubtractorParameter::new("learning_rate", self.settings.learning_rate, 0.005),
+        ];
+        if let Some(adaptive) = self.settings.adaptive_threshold {
+            parameters.push(SubtractorParameter::new(
+                "noise_factor",
+                adaptive.noise_factor,
+                0.5,
+            ));
+            parameters.push(SubtractorParameter::new(
+                "min_threshold",
+                adaptive.min_threshold,
+                5.0,
+            ));
+        }
+        parameters
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match (name, self.settings.adaptive_threshold.as_mut()) {
+            ("threshold_per_channel", _) => self.settings.threshold_per_channel = value,
+            ("learning_rate", _) => self.settings.learning_rate = value.min(1.0),
+            ("noise_factor", Some(adaptive)) => adaptive.noise_factor = value,
+            ("min_threshold", Some(adaptive)) => adaptive.min_threshold = value,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn save_model(&self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        if self.is_collecting() || self.background_approximation.empty() {
+            return Err("the reference isn't built yet".into());
+        }
+        let path = Path::new(dir).join(MODEL_FILE);
+        let mut storage =
+            FileStorage::new(&path.to_string_lossy(), FileStorage_Mode::WRITE.into(), "")?;
+        storage.write_mat("background", &self.background_approximation)?;
+        if let Some(noise) = self.noise.as_ref() {
+            storage.write_mat("noise", noise)?;
+        }
+        storage.release()?;
+        Ok(true)
+    }
+
+    fn load_model(&mut self, dir: &str) -> std::result::Result<bool, Box<dyn Error>> {
+        let path = Path::new(dir).join(MODEL_FILE);
+        if !path.exists() {
+            return Ok(false);
+        }
+        let storage = FileStorage::new(&path.to_string_lossy(), FileStorage_Mode::READ.into(), "")?;
+        let background = storage.get("background")?.mat()?;
+        if background.empty() {
+            return Err(format!("{} has no background", path.display()).into());
+        }
+        let noise = storage.get("noise")?;
+        self.noise = match noise.empty()? {
+            true => None,
+            false => Some(noise.mat()?),
+        };
+        self.background_approximation = background;
+        self.reference_frames.clear();
+        Ok(true)
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        let mut stages: Vec<(String, Mat)> = self
+            .difference
+            .take()
+            .map(|difference| vec![("difference".to_string(), difference)])
+            .unwrap_or_default();
+        if let Some(noise) = self.noise.as_ref() {
+            stages.push(("noise".to_string(), noise.clone()));
+        }
+        stages
     }
 }
@@ -1,4 +1,5 @@
-use crate::types::BackgroundSubtractor;
+use crate::bg_subtract::confidence::confidence_from_score;
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
 use opencv::Result;
 use opencv::core::{
     Mat, MatExpr, MatExprTraitConst, Vector, cart_to_polar, greater_than_mat_f64, split,
@@ -28,6 +29,8 @@ pub enum OfOutputType {
 pub struct OfSubtractor {
     settings: OfSettings,
     prev_img: Option<Mat>,
+    flow: Option<Mat>,
+    confidence: Option<Mat>,
 }
 
 impl OfSubtractor {
@@ -35,6 +38,8 @@ impl OfSubtractor {
         Ok(OfSubtractor {
             settings,
             prev_img: None,
+            flow: None,
+            confidence: None,
         })
     }
 }
@@ -93,10 +98,61 @@ impl BackgroundSubtractor for OfSubtractor {
             OfOutputType::Magnitude => calc_flow_magnitude(flow)?,
             OfOutputType::YDirection => calc_jumps(flow)?,
         };
-        greater_than_mat_f64(&magnitude, self.settings.threshold)
+        let mask = greater_than_mat_f64(&magnitude, self.settings.threshold);
+        self.confidence = Some(confidence_from_score(&magnitude, self.settings.threshold)?);
+        self.flow = Some(magnitude);
+        mask
     }
 
     fn reset(&mut self, _background_img: Mat) {
         self.prev_img = None;
     }
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: self.confidence.take(),
+            ..Default::default()
+        }
+    }
+
+    fn calibration_target(&self) -> Option<(&'static str, &'static str)> {
+        Some(("of_settings", "threshold"))
+    }
+
+    fn threshold_score(&self) -> Option<Mat> {
+        self.flow.clone()
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("threshold", self.settings.threshold, 1.0),
+            SubtractorParameter::new("win_size", self.settings.win_size as f64, 2.0),
+            SubtractorParameter::new("scales", self.settings.scales as f64, 1.0),
+            SubtractorParameter::new("iterations", self.settings.iterations as f64, 1.0),
+        ]
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        //farneback needs at least one of each
+        let count = value.round().max(1.0) as i32;
+        match name {
+            "threshold" => self.settings.threshold = value,
+            "win_size" => self.settings.win_size = count,
+            "scales" => self.settings.scales = count,
+            "iterations" => self.settings.iterations = count,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        self.flow
+            .take()
+            .map(|flow| vec![("flow".to_string(), flow)])
+            .unwrap_or_default()
+    }
 }
@@ -0,0 +1,337 @@
+use std::error::Error;
+
+use opencv::core::{
+    CV_8UC3, Mat, MatExpr, Point, Rect, Scalar, Size, StsError, bitwise_not, in_range, no_array,
+};
+use opencv::imgproc::{LINE_8, ellipse, rectangle};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::shapes::{draw_person, fill_polygon};
+use crate::config::load_config;
+use crate::types::BackgroundSubtractor;
+
+#[derive(Deserialize, Clone)]
+pub struct ScriptedSettings {
+    pub script_path: String,
+}
+
+//Polygon points are relative to the center and scaled by the size of the shape
+#[derive(Deserialize, Clone, Debug)]
+pub enum ShapeType {
+    Rectangle,
+    Ellipse,
+    Person,
+    Polygon(Vec<(f64, f64)>),
+}
+
+//position is the center of the shape. Position and size are relative to the image size,
+//with (0, 0) being the top left corner of the camera image
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f64,
+    pub position: (f64, f64),
+    pub size: (f64, f64),
+}
+
+//A shape exists from its first to its last keyframe and moves linearly in between.
+//A shape with a single keyframe is always there
+#[derive(Deserialize, Clone, Debug)]
+pub struct ScriptedShape {
+    pub shape: ShapeType,
+    pub color: [u8; 3],
+    pub keyframes: Vec<Keyframe>,
+}
+
+//Synthetic scene for repeatable tests. Time advances by one frame per image, so the
+//same script always produces the same images. Colors are BGR like camera images
+#[derive(Deserialize, Clone, Debug)]
+pub struct Script {
+    pub image_size: (i32, i32),
+    pub fps: f64,
+    pub looped: bool,
+    pub background: [u8; 3],
+    pub shapes: Vec<ScriptedShape>,
+}
+
+fn to_scalar(color: [u8; 3]) -> Scalar {
+    Scalar::new(color[0] as f64, color[1] as f64, color[2] as f64, 0.0)
+}
+
+fn lerp(a: (f64, f64), b: (f64, f64), t: f64) -> (f64, f64) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+impl ScriptedShape {
+    //interpolated keyframe at time t, None if the shape doesn't exist at t
+    fn sample(&self, t: f64) -> Option<Keyframe> {
+        let (first, last) = (self.keyframes.first()?, self.keyframes.last()?);
+        if self.keyframes.len() == 1 {
+            return Some(*first);
+        }
+        if t < first.time || t > last.time {
+            return None;
+        }
+        let next = self
+            .keyframes
+            .iter()
+            .position(|key| key.time >= t)
+            .unwrap_or(self.keyframes.len() - 1)
+            .max(1);
+        let (start, end) = (self.keyframes[next - 1], self.keyframes[next]);
+        let blend = match end.time > start.time {
+            true => (t - start.time) / (end.time - start.time),
+            false => 1.0,
+        };
+        Some(Keyframe {
+            time: t,
+            position: lerp(start.position, end.position, blend),
+            size: lerp(start.size, end.size, blend),
+        })
+    }
+
+    //shapes are drawn without antialiasing, so the mask matches the image exactly
+    fn draw(&self, img: &mut Mat, t: f64) -> opencv::Result<()> {
+        let Some(key) = self.sample(t) else {
+            return Ok(());
+        };
+        let (width, height) = (img.cols() as f64, img.rows() as f64);
+        let center = Point::new(
+            (key.position.0 * width) as i32,
+            (key.position.1 * height) as i32,
+        );
+        let size = (key.size.0 * width, key.size.1 * height);
+        let color = to_scalar(self.color);
+        match &self.shape {
+            ShapeType::Rectangle => rectangle(
+                img,
+                Rect::new(
+                    center.x - (size.0 / 2.0) as i32,
+                    center.y - (size.1 / 2.0) as i32,
+                    size.0 as i32,
+                    size.1 as i32,
+                ),
+                color,
+                -1,
+                LINE_8,
+                0,
+            ),
+            ShapeType::Ellipse => ellipse(
+                img,
+                center,
+                Size::new((size.0 / 2.0) as i32, (size.1 / 2.0) as i32),
+                0.0,
+                0.0,
+                360.0,
+                color,
+                -1,
+                LINE_8,
+                0,
+            ),
+            ShapeType::Person => draw_person(img, center, size.1, color),
+            ShapeType::Polygon(points) => fill_polygon(img, points, center, size, color),
+        }
+    }
+}
+
+impl Script {
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.fps <= 0.0 {
+            return Err("script fps has to be positive".into());
+        }
+        if self.shapes.iter().any(|shape| shape.keyframes.is_empty()) {
+            return Err("every scripted shape needs at least one keyframe".into());
+        }
+        if self
+            .shapes
+            .iter()
+            .any(|shape| shape.color == self.background)
+        {
+            return Err("scripted shapes can't have the background color".into());
+        }
+        Ok(())
+    }
+
+    fn duration(&self) -> f64 {
+        self.shapes
+            .iter()
+            .filter_map(|shape| shape.keyframes.last())
+            .map(|key| key.time)
+            .fold(0.0, f64::max)
+    }
+
+    //time of the given frame. Looped scripts start over after their last keyframe
+    pub fn frame_time(&self, frame: u64) -> f64 {
+        let t = frame as f64 / self.fps;
+        let duration = self.duration();
+        match self.looped && duration > 0.0 {
+            true => t % duration,
+            false => t,
+        }
+    }
+
+    //fake camera image of the scene at time t
+    pub fn render(&self, t: f64) -> opencv::Result<Mat> {
+        let (width, height) = self.image_size;
+        let mut img =
+            Mat::new_rows_cols_with_default(height, width, CV_8UC3, to_scalar(self.background))?;
+        for shape in self.shapes.iter() {
+            shape.draw(&mut img, t)?;
+        }
+        Ok(img)
+    }
+}
+
+pub fn load_script(path: &str) -> Result<Script, Box<dyn Error>> {
+    let script: Script = load_config(path)?;
+    script.validate()?;
+    Ok(script)
+}
+
+//Mask of a scripted scene. Everything that doesn't have the background color of the
+//script is a silhouette, so the mask matches the shapes of the fake camera image exactly
+pub struct ScriptedSubtractor {
+    background: Scalar,
+}
+
+impl ScriptedSubtractor {
+    pub fn new(settings: ScriptedSettings) -> opencv::Result<ScriptedSubtractor> {
+        let script = load_script(&settings.script_path)
+            .map_err(|err| opencv::Error::new(StsError, err.to_string()))?;
+        Ok(ScriptedSubtractor {
+            background: to_scalar(script.background),
+        })
+    }
+}
+
+impl BackgroundSubtractor for ScriptedSubtractor {
+    fn apply(&mut self, input_img: Mat) -> opencv::Result<MatExpr> {
+        let mut is_background = Mat::default();
+        in_range(
+            &input_img,
+            &self.background,
+            &self.background,
+            &mut is_background,
+        )?;
+        let mut mask = Mat::default();
+        bitwise_not(&is_background, &mut mask, &no_array())?;
+        MatExpr::from_mat(&mask)
+    }
+
+    fn reset(&mut self, _background_img: Mat) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, position: (f64, f64), size: (f64, f64)) -> Keyframe {
+        Keyframe {
+            time,
+            position,
+            size,
+        }
+    }
+
+    fn rectangle(keyframes: Vec<Keyframe>) -> ScriptedShape {
+        ScriptedShape {
+            shape: ShapeType::Rectangle,
+            color: [255, 255, 255],
+            keyframes,
+        }
+    }
+
+    fn script(looped: bool, shapes: Vec<ScriptedShape>) -> Script {
+        Script {
+            image_size: (100, 50),
+            fps: 10.0,
+            looped,
+            background: [0, 0, 0],
+            shapes,
+        }
+    }
+
+    #[test]
+    fn keyframes_are_interpolated_linearly() {
+        let shape = rectangle(vec![
+            key(0.0, (0.0, 0.0), (0.1, 0.1)),
+            key(2.0, (1.0, 0.5), (0.3, 0.1)),
+            key(3.0, (1.0, 1.0), (0.3, 0.1)),
+        ]);
+        let sample = shape.sample(1.0).unwrap();
+        assert_eq!(sample.position, (0.5, 0.25));
+        assert!((sample.size.0 - 0.2).abs() < 1e-9);
+        assert_eq!(shape.sample(2.5).unwrap().position, (1.0, 0.75));
+        assert_eq!(shape.sample(3.0).unwrap().position, (1.0, 1.0));
+    }
+
+    #[test]
+    fn shapes_only_exist_between_their_keyframes() {
+        let shape = rectangle(vec![
+            key(1.0, (0.5, 0.5), (0.1, 0.1)),
+            key(2.0, (0.5, 0.5), (0.1, 0.1)),
+        ]);
+        assert!(shape.sample(0.5).is_none());
+        assert!(shape.sample(1.0).is_some());
+        assert!(shape.sample(2.5).is_none());
+    }
+
+    #[test]
+    fn single_keyframe_shapes_are_always_there() {
+        let shape = rectangle(vec![key(5.0, (0.2, 0.3), (0.1, 0.1))]);
+        for t in [0.0, 5.0, 100.0] {
+            assert_eq!(shape.sample(t).unwrap().position, (0.2, 0.3));
+        }
+    }
+
+    #[test]
+    fn looped_scripts_start_over() {
+        let shapes = vec![rectangle(vec![
+            key(0.0, (0.5, 0.5), (0.1, 0.1)),
+            key(2.0, (0.5, 0.5), (0.1, 0.1)),
+        ])];
+        assert_eq!(script(true, shapes.clone()).frame_time(25), 0.5);
+        assert_eq!(script(false, shapes).frame_time(25), 2.5);
+    }
+
+    #[test]
+    fn invalid_scripts_are_rejected() {
+        let valid = script(
+            false,
+            vec![rectangle(vec![key(0.0, (0.5, 0.5), (0.1, 0.1))])],
+        );
+        assert!(valid.validate().is_ok());
+
+        let mut no_fps = valid.clone();
+        no_fps.fps = 0.0;
+        assert!(no_fps.validate().is_err());
+
+        let mut no_keyframes = valid.clone();
+        no_keyframes.shapes[0].keyframes.clear();
+        assert!(no_keyframes.validate().is_err());
+
+        let mut background_color = valid;
+        background_color.shapes[0].color = [0, 0, 0];
+        assert!(background_color.validate().is_err());
+    }
+
+    #[test]
+    fn mask_matches_the_rendered_shapes() {
+        let script = script(
+            false,
+            vec![rectangle(vec![key(0.0, (0.5, 0.5), (0.2, 0.2))])],
+        );
+        let mut subtractor = ScriptedSubtractor {
+            background: to_scalar(script.background),
+        };
+        let mask = subtractor
+            .apply(script.render(0.0).unwrap())
+            .unwrap()
+            .to_mat()
+            .unwrap();
+        assert_eq!(*mask.at_2d::<u8>(25, 50).unwrap(), 255);
+        assert_eq!(*mask.at_2d::<u8>(5, 5).unwrap(), 0);
+        //the rectangle is 20x10 pixels
+        assert_eq!(opencv::core::count_non_zero(&mask).unwrap(), 20 * 10);
+    }
+}
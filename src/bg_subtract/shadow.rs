@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+
+use opencv::Result;
+use opencv::core::{
+    AlgorithmHint, BORDER_DEFAULT, CV_32F, Mat, MatExpr, Size, bitwise_not, divide2, flip,
+    greater_than_mat_mat, min, multiply, no_array,
+};
+use opencv::imgproc::{
+    COLOR_BGR2GRAY, COLOR_RGBA2GRAY, INTER_LINEAR, accumulate_weighted, cvt_color, gaussian_blur,
+    resize,
+};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+use crate::bg_subtract::confidence::confidence_from_score;
+use crate::types::{BackgroundSubtractor, MaskChannels, SubtractorParameter};
+
+//drop_threshold is the relative drop in brightness that counts as a shadow. ambient is the
+//brightness of the wall without projection relative to a white projection. Camera images
+//lag behind the renderer, so the darkest of the last latency_frames rendered frames is used
+#[derive(Deserialize, Clone, Copy)]
+pub struct ShadowSettings {
+    drop_threshold: f64,
+    ambient: f64,
+    blur: f64,
+    learning_rate: f64,
+    latency_frames: usize,
+}
+
+const MIN_AMBIENT: f64 = 0.01;
+
+//frames are read back from opengl, so they are upside down and mirrored compared to
+//the camera, just like the mask is compared to the screen
+fn rendered_luminance(frame: &Mat) -> Result<Mat> {
+    let mut flipped = Mat::default();
+    flip(frame, &mut flipped, -1)?;
+    let mut gray = Mat::default();
+    cvt_color(
+        &flipped,
+        &mut gray,
+        COLOR_RGBA2GRAY,
+        1,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    let mut res = Mat::default();
+    gray.convert_to(&mut res, CV_32F, 1.0 / 255.0, 0.0)?;
+    Ok(res)
+}
+
+//For setups where the camera looks at the projection wall and players are shadows on it.
+//The brightness of every wall pixel is modeled as gain * (projected + ambient). The gain is
+//learned from the reference image, the projected brightness comes from the frames the
+//renderer drew. Without rendered frames the projection is assumed to be constant
+pub struct ShadowSubtractor {
+    settings: ShadowSettings,
+    gain: Mat,
+    rendered: VecDeque<Mat>,
+    luminance: Option<Mat>,
+    expected: Option<Mat>,
+    confidence: Option<Mat>,
+}
+
+impl ShadowSubtractor {
+    pub fn new(settings: ShadowSettings) -> ShadowSubtractor {
+        ShadowSubtractor {
+            settings,
+            gain: Mat::default(),
+            rendered: VecDeque::new(),
+            luminance: None,
+            expected: None,
+            confidence: None,
+        }
+    }
+
+    fn blur(&self, img: &Mat) -> Result<Mat> {
+        if self.settings.blur <= 0.0 {
+            return Ok(img.clone());
+        }
+        let mut res = Mat::default();
+        gaussian_blur(
+            img,
+            &mut res,
+            Size::new(0, 0),
+            self.settings.blur,
+            self.settings.blur,
+            BORDER_DEFAULT,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        Ok(res)
+    }
+
+    //blurred brightness between 0 and 1, the blur hides small misalignments
+    fn luminance(&self, img: &Mat) -> Result<Mat> {
+        let gray = match img.channels() {
+            1 => img.clone(),
+            _ => {
+                let mut gray = Mat::default();
+                cvt_color(
+                    img,
+                    &mut gray,
+                    COLOR_BGR2GRAY,
+                    1,
+                    AlgorithmHint::ALGO_HINT_DEFAULT,
+                )?;
+                gray
+            }
+        };
+        let mut res = Mat::default();
+        gray.convert_to(&mut res, CV_32F, 1.0 / 255.0, 0.0)?;
+        self.blur(&res)
+    }
+
+    //projected brightness plus ambient light in camera coordinates
+    fn illumination(&self, size: Size) -> Result<Mat> {
+        let projected = match self.rendered.iter().next() {
+            None => Mat::ones_size(size, CV_32F)?.to_mat()?,
+            Some(first) => {
+                let mut darkest = first.clone();
+                for frame in self.rendered.iter().skip(1) {
+                    let mut res = Mat::default();
+                    min(&darkest, frame, &mut res)?;
+                    darkest = res;
+                }
+                let mut resized = Mat::default();
+                resize(&darkest, &mut resized, size, 0.0, 0.0, INTER_LINEAR)?;
+                self.blur(&resized)?
+            }
+        };
+        //some ambient light keeps black projections from dividing by zero
+        let mut res = Mat::default();
+        projected.convert_to(&mut res, -1, 1.0, self.settings.ambient.max(MIN_AMBIENT))?;
+        Ok(res)
+    }
+
+    fn gain_from(&self, luminance: &Mat, illumination: &Mat) -> Result<Mat> {
+        let mut gain = Mat::default();
+        divide2(luminance, illumination, &mut gain, 1.0, -1)?;
+        Ok(gain)
+    }
+}
+
+impl BackgroundSubtractor for ShadowSubtractor {
+    fn apply(&mut self, input_img: Mat) -> Result<MatExpr> {
+        let luminance = self.luminance(&input_img)?;
+        let illumination = self.illumination(luminance.size()?)?;
+        if self.gain.size()? != luminance.size()? {
+            self.gain = self.gain_from(&luminance, &illumination)?;
+        }
+
+        let mut expected = Mat::default();
+        multiply(&self.gain, &illumination, &mut expected, 1.0, -1)?;
+        let mut limit = Mat::default();
+        expected.convert_to(&mut limit, -1, 1.0 - self.settings.drop_threshold, 0.0)?;
+        let mask = greater_than_mat_mat(&limit, &luminance)?;
+
+        //relative drop of the brightness, drop_threshold ends up at 0.5
+        let mut ratio = Mat::default();
+        divide2(&luminance, &expected, &mut ratio, 1.0, -1)?;
+        let mut drop = Mat::default();
+        ratio.convert_to(&mut drop, -1, -1.0, 1.0)?;
+        self.confidence = Some(confidence_from_score(&drop, self.settings.drop_threshold)?);
+
+        //the wall slowly follows changes of the room light outside of shadows
+        if self.settings.learning_rate > 0.0 {
+            let mut lit = Mat::default();
+            bitwise_not(&mask.to_mat()?, &mut lit, &no_array())?;
+            let gain = self.gain_from(&luminance, &illumination)?;
+            accumulate_weighted(&gain, &mut self.gain, self.settings.learning_rate, &lit)?;
+        }
+        self.luminance = Some(luminance);
+        self.expected = Some(expected);
+        Ok(mask)
+    }
+
+    fn reset(&mut self, background_img: Mat) {
+        let gain = self.luminance(&background_img).and_then(|luminance| {
+            let illumination = self.illumination(luminance.size()?)?;
+            self.gain_from(&luminance, &illumination)
+        });
+        match gain {
+            Ok(gain) => self.gain = gain,
+            Err(error) => eprintln!("could not set shadow reference {error}"),
+        }
+    }
+
+    fn takes_reference(&self) -> bool {
+        true
+    }
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels {
+            confidence: self.confidence.take(),
+            ..Default::default()
+        }
+    }
+
+    fn rendered_frame(&mut self, frame: Mat) {
+        match rendered_luminance(&frame) {
+            Ok(frame) => {
+                self.rendered.push_back(frame);
+                while self.rendered.len() > self.settings.latency_frames.max(1) {
+                    self.rendered.pop_front();
+                }
+            }
+            Err(error) => eprintln!("could not use rendered frame {error}"),
+        }
+    }
+
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        vec![
+            SubtractorParameter::new("drop_threshold", self.settings.drop_threshold, 0.05),
+            SubtractorParameter::new("ambient", self.settings.ambient, 0.05),
+            SubtractorParameter::new("blur", self.settings.blur, 1.0),
+            SubtractorParameter::new("learning_rate", self.settings.learning_rate, 0.005),
+            SubtractorParameter::new("latency_frames", self.settings.latency_frames as f64, 1.0),
+        ]
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match name {
+            "drop_threshold" => self.settings.drop_threshold = value.min(1.0),
+            "ambient" => self.settings.ambient = value,
+            "blur" => self.settings.blur = value,
+            "learning_rate" => self.settings.learning_rate = value.min(1.0),
+            "latency_frames" => self.settings.latency_frames = value.round() as usize,
+            _ => return Err(format!("unknown parameter {}", name).into()),
+        }
+        Ok(())
+    }
+
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        [
+            ("luminance", self.luminance.take()),
+            ("expected", self.expected.take()),
+        ]
+        .into_iter()
+        .filter_map(|(name, stage)| stage.map(|stage| (name.to_string(), stage)))
+        .collect()
+    }
+}
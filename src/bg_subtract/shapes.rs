@@ -0,0 +1,62 @@
+use opencv::Result;
+use opencv::core::{Mat, Point, Scalar, Vector};
+use opencv::imgproc::{LINE_8, circle, fill_poly};
+
+//outline of a person with height 1, centered on the torso. y points down like in the mask
+const PERSON_OUTLINE: [(f64, f64); 15] = [
+    (-0.12, -0.29),
+    (0.12, -0.29),
+    (0.26, 0.02),
+    (0.21, 0.04),
+    (0.11, -0.14),
+    (0.11, 0.08),
+    (0.14, 0.5),
+    (0.04, 0.5),
+    (0.0, 0.16),
+    (-0.04, 0.5),
+    (-0.14, 0.5),
+    (-0.11, 0.08),
+    (-0.11, -0.14),
+    (-0.21, 0.04),
+    (-0.26, 0.02),
+];
+const PERSON_HEAD: ((f64, f64), f64) = ((0.0, -0.39), 0.09);
+
+//fills the polygon given by points relative to center, scaled by size (width, height)
+pub fn fill_polygon(
+    img: &mut Mat,
+    points: &[(f64, f64)],
+    center: Point,
+    size: (f64, f64),
+    color: Scalar,
+) -> Result<()> {
+    let outline: Vector<Point> = points
+        .iter()
+        .map(|(x, y)| {
+            Point::new(
+                center.x + (x * size.0) as i32,
+                center.y + (y * size.1) as i32,
+            )
+        })
+        .collect();
+    let outlines: Vector<Vector<Point>> = Vector::from_iter([outline]);
+    fill_poly(img, &outlines, color, LINE_8, 0, Point::default())
+}
+
+//draws a person shaped silhouette of the given height in pixels
+pub fn draw_person(img: &mut Mat, center: Point, height: f64, color: Scalar) -> Result<()> {
+    fill_polygon(img, &PERSON_OUTLINE, center, (height, height), color)?;
+    let ((x, y), radius) = PERSON_HEAD;
+    circle(
+        img,
+        Point::new(
+            center.x + (x * height) as i32,
+            center.y + (y * height) as i32,
+        ),
+        (radius * height) as i32,
+        color,
+        -1,
+        LINE_8,
+        0,
+    )
+}
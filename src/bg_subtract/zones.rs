@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::time::SystemTime;
+
+use opencv::core::{CV_8U, Mat, Point, Scalar, Size, Vector};
+use opencv::imgproc::{LINE_8, fill_poly};
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::load_config;
+
+//Parts of the camera image that never count as silhouettes. Points are relative to the
+//image size with (0, 0) being the top left corner of the camera image, so zones don't
+//depend on the camera resolution. Without a roi the whole image is used
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Zones {
+    pub exclusions: Vec<Vec<(f64, f64)>>,
+    pub roi: Option<Vec<(f64, f64)>>,
+}
+
+//a missing file means there are no zones yet
+pub fn load_zones(path: &str) -> Result<Zones, Box<dyn Error>> {
+    match std::path::Path::new(path).exists() {
+        true => load_config(path),
+        false => Ok(Zones::default()),
+    }
+}
+
+pub fn save_zones(path: &str, zones: &Zones) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, toml::to_string(zones)?)?;
+    Ok(())
+}
+
+fn to_pixels(polygon: &[(f64, f64)], size: Size) -> Vector<Point> {
+    polygon
+        .iter()
+        .map(|(x, y)| {
+            Point::new(
+                (x * size.width as f64).round() as i32,
+                (y * size.height as f64).round() as i32,
+            )
+        })
+        .collect()
+}
+
+fn fill(img: &mut Mat, polygon: &[(f64, f64)], value: f64) -> opencv::Result<()> {
+    if polygon.len() < 3 {
+        return Ok(());
+    }
+    let size = img.size()?;
+    let polygons: Vector<Vector<Point>> = Vector::from_iter([to_pixels(polygon, size)]);
+    fill_poly(
+        img,
+        &polygons,
+        Scalar::all(value),
+        LINE_8,
+        0,
+        Point::new(0, 0),
+    )
+}
+
+impl Zones {
+    //255 where pixels are excluded
+    pub fn excluded(&self, size: Size) -> opencv::Result<Mat> {
+        let mut excluded = match self.roi.as_ref() {
+            Some(roi) => {
+                let mut excluded = Mat::new_size_with_default(size, CV_8U, Scalar::all(255.0))?;
+                fill(&mut excluded, roi, 0.0)?;
+                excluded
+            }
+            None => Mat::zeros_size(size, CV_8U)?.to_mat()?,
+        };
+        for polygon in self.exclusions.iter() {
+            fill(&mut excluded, polygon, 255.0)?;
+        }
+        Ok(excluded)
+    }
+}
+
+//The zones of the pipeline. The file is reloaded when it changes, so zones drawn in the
+//calibration game apply right away
+pub struct ZoneMask {
+    path: String,
+    zones: Zones,
+    modified: Option<SystemTime>,
+    excluded: Mat,
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ZoneMask {
+    //broken zone files are reported and ignored, the masks are still usable without zones
+    pub fn new(path: &str) -> ZoneMask {
+        let zones = load_zones(path).unwrap_or_else(|error| {
+            eprintln!("could not load zones from {}: {}", path, error);
+            Zones::default()
+        });
+        ZoneMask {
+            path: path.to_string(),
+            zones,
+            modified: modified_time(path),
+            excluded: Mat::default(),
+        }
+    }
+
+    fn reload_if_changed(&mut self) {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match load_zones(&self.path) {
+            Ok(zones) => {
+                println!("reloaded zones from {}", self.path);
+                self.zones = zones;
+                self.excluded = Mat::default();
+            }
+            Err(error) => eprintln!("could not reload zones from {}: {}", self.path, error),
+        }
+    }
+
+    //clears the excluded pixels of a mask or any other image of the camera size
+    pub fn apply(&mut self, img: &mut Mat) -> opencv::Result<()> {
+        self.reload_if_changed();
+        if self.zones.exclusions.is_empty() && self.zones.roi.is_none() {
+            return Ok(());
+        }
+        if self.excluded.size()? != img.size()? {
+            self.excluded = self.zones.excluded(img.size()?)?;
+        }
+        img.set_to(&Scalar::all(0.0), &self.excluded)?;
+        Ok(())
+    }
+}
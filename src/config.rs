@@ -1,18 +1,40 @@
 use crate::display::minimap::MinimapState;
+use crate::display::post_process::PostStage;
+use crate::display::window::{FullscreenMode, MonitorSelector};
+use crate::game::util::LiveViewMode;
+use crate::threads::CameraBackend;
 use crate::types::{GameType, SubtractorType};
 use serde::Deserialize;
 use serde::de::DeserializeOwned;
 use toml;
 
-#[derive(Deserialize, Clone)]
+//every property but the device index is optional, unset ones keep the driver default
+#[derive(Deserialize, Clone, Debug)]
 pub struct CameraConfig {
     pub device_index: i32,
+    #[serde(default)]
+    pub backend: CameraBackend,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub fps: Option<f64>,
+    //four character code of the pixel format, e.g. MJPG
+    pub fourcc: Option<String>,
+    //setting these turns the automatic exposure and white balance off
+    pub exposure: Option<f64>,
+    pub gain: Option<f64>,
+    //color temperature in kelvin
+    pub white_balance: Option<f64>,
+    pub autofocus: Option<bool>,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct BgSubConfig {
     pub subtractor_type: SubtractorType,
     pub settings_path: String,
+    //exclusion zones and roi, drawn in the calibration game
+    pub zones_path: String,
+    //saved background models, loaded at startup instead of taking a new reference
+    pub model_dir: String,
 }
 
 #[derive(Deserialize, Clone)]
@@ -20,6 +42,50 @@ pub struct MinimapConfig {
     pub show: MinimapState,
     pub position: (f32, f32),
     pub dims: (f32, f32),
+    pub live_view_mode: LiveViewMode,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CalibrationConfig {
+    pub live_view_mode: LiveViewMode,
+    pub edit_zones_key: String,
+    pub roi_key: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WindowConfig {
+    pub monitor: Option<MonitorSelector>,
+    pub fullscreen: FullscreenMode,
+    pub resolution: Option<(u32, u32)>,
+    pub vsync: bool,
+    pub hide_cursor: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct OperatorConfig {
+    pub enabled: bool,
+    pub window_config: WindowConfig,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostProcessConfig {
+    pub stages: Vec<PostStage>,
+}
+
+//warp of the output. The warp is loaded from and saved to file. step is how far a
+//handle moves per key press, mesh_size the number of control points in mesh mode
+#[derive(Deserialize, Clone)]
+pub struct WarpConfig {
+    pub file: String,
+    pub step: f32,
+    pub mesh_size: (usize, usize),
+}
+
+//shader files are checked for changes every interval_ms milliseconds
+#[derive(Deserialize, Clone)]
+pub struct ShaderReloadConfig {
+    pub enabled: bool,
+    pub interval_ms: u64,
 }
 
 #[derive(Deserialize, Clone)]
@@ -27,6 +93,18 @@ pub struct KeyConfig {
     pub quit_key: String,
     pub reset_key: String,
     pub toggle_minimap_key: String,
+    pub next_monitor_key: String,
+    pub next_operator_stage_key: String,
+    pub warp_adjust_key: String,
+    pub warp_mode_key: String,
+    pub warp_reset_key: String,
+    pub toggle_stamp_key: String,
+    pub next_subtractor_key: String,
+    pub next_parameter_key: String,
+    pub increase_parameter_key: String,
+    pub decrease_parameter_key: String,
+    pub calibrate_key: String,
+    pub save_model_key: String,
 }
 
 #[derive(Deserialize, Clone)]
@@ -42,6 +120,12 @@ pub struct PlatoConfig {
     pub background_subtractor_config: BgSubConfig,
     pub game_type: GameType,
     pub minimap_config: MinimapConfig,
+    pub calibration_config: CalibrationConfig,
+    pub window_config: WindowConfig,
+    pub operator_config: OperatorConfig,
+    pub post_process_config: PostProcessConfig,
+    pub warp_config: WarpConfig,
+    pub shader_reload_config: ShaderReloadConfig,
     pub key_config: KeyConfig,
     pub sound_config: SoundConfig,
 }
@@ -67,3 +151,51 @@ pub fn load_config<T: DeserializeOwned>(path: &str) -> Result<T, Box<dyn std::er
     let contents = open_file(path)?;
     Ok(toml::from_str(&contents)?)
 }
+
+//checks what the types can't express, right after loading so bad values stop the start
+pub fn validate_config(config: &PlatoConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let (cols, rows) = config.warp_config.mesh_size;
+    if cols < 2 || rows < 2 {
+        return Err(format!(
+            "warp_config.mesh_size is {}x{}, the warp mesh needs at least 2x2 control points",
+            cols, rows
+        )
+        .into());
+    }
+    Ok(())
+}
+
+//changes a single value of a toml file in place, so comments and formatting are kept
+pub fn update_config_value(
+    path: &str,
+    section: &str,
+    key: &str,
+    value: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = open_file(path)?;
+    let mut current_section = "";
+    let mut found = false;
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            current_section = trimmed.trim_matches(['[', ']']);
+        }
+        let is_key = trimmed
+            .split_once('=')
+            .is_some_and(|(name, _)| name.trim() == key);
+        match !found && is_key && current_section == section {
+            true => {
+                //debug formatting keeps the decimal point, so the value stays a float
+                lines.push(format!("{}= {:?}", key, value));
+                found = true;
+            }
+            false => lines.push(line.to_string()),
+        }
+    }
+    if !found {
+        return Err(format!("{} is not in [{}] of {}", key, section, path).into());
+    }
+    std::fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
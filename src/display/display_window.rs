@@ -2,36 +2,57 @@ use opencv::prelude::*;
 
 use crate::PlatoConfig;
 use crate::display::minimap::{Minimap, MinimapState, rotate_state};
+use crate::display::operator::OperatorWindow;
+use crate::display::post_process::PostProcessor;
 use crate::display::timestep::TimeStep;
+use crate::display::window::{build_window, move_to_next_monitor};
 use crate::threads::try_sending;
-use crate::types::{GameTrait, thread_types::*};
+use crate::types::{
+    GameTrait, MaskChannels, PointerState, SubtractorParameter, SubtractorType, next_subtractor,
+    thread_types::*,
+};
 use std::sync::mpsc::{Receiver, SyncSender, TryRecvError};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 extern crate glium;
 use glium::Surface;
 // Use the re-exported winit dependency to avoid version mismatches.
-// Requires the `simple_window_builder` feature.
 use glium::winit;
 
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
-use winit::event::{ElementState, KeyEvent};
+use winit::event::{ElementState, KeyEvent, MouseButton};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
-use winit::keyboard::Key;
+use winit::keyboard::{Key, NamedKey};
 use winit::platform::pump_events::{EventLoopExtPumpEvents, PumpStatus};
 use winit::window::{Window, WindowId};
 
 pub type DisplayType = glium::Display<glium::glutin::surface::WindowSurface>;
+//games draw into an offscreen target, which is post processed before it is shown
+pub type RenderTarget<'a> = glium::framebuffer::SimpleFrameBuffer<'a>;
 
 struct PlatoApp {
     pipeline_control_queue: SyncSender<PipelineMessage>,
     window: Window,
     display: DisplayType,
     minimap: Minimap,
+    post_process: PostProcessor,
+    operator: Option<OperatorWindow>,
     game: Box<dyn GameTrait>,
     config: PlatoConfig,
     timestep: TimeStep,
+    last_shader_check: Instant,
+    //last cursor position on the projector in opengl coordinates
+    cursor: (f32, f32),
+    //mouse input for the game and the mouse subtractor. It's only sent if it changed
+    pointer: PointerState,
+    pointer_changed: bool,
+    //tunable parameters of the active subtractor, as reported by the pipeline
+    parameters: Vec<SubtractorParameter>,
+    selected_parameter: usize,
+    //SetParameter messages sent. Until the pipeline handled all of them its parameters
+    //are older than the local ones
+    parameter_changes: u64,
 }
 
 impl PlatoApp {
@@ -41,18 +62,32 @@ impl PlatoApp {
         game: Box<dyn GameTrait>,
         config: PlatoConfig,
     ) -> Result<PlatoApp, Box<dyn std::error::Error>> {
-        let (window, display) =
-            glium::backend::glutin::SimpleWindowBuilder::new().build(event_loop);
+        let (window, display) = build_window(event_loop, "Platos Beamer", &config.window_config)?;
         let minimap = Minimap::new(&display, &config)?;
+        let post_process =
+            PostProcessor::new(&display, &config.post_process_config, &config.warp_config)?;
+        let operator = match config.operator_config.enabled {
+            true => Some(OperatorWindow::new(event_loop, &config)?),
+            false => None,
+        };
         let timestep = TimeStep::new();
         let mut app = PlatoApp {
             pipeline_control_queue: pipeline_control_queue.clone(),
             window,
             display,
             minimap,
+            post_process,
+            operator,
             game,
             config,
             timestep,
+            last_shader_check: Instant::now(),
+            cursor: (0_f32, 0_f32),
+            pointer: PointerState::default(),
+            pointer_changed: false,
+            parameters: Vec::new(),
+            selected_parameter: 0,
+            parameter_changes: 0,
         };
         app.init()?;
         Ok(app)
@@ -69,27 +104,207 @@ impl PlatoApp {
         self.timestep.reset();
     }
 
-    fn update(&mut self, image: Mat, mask: Mat) -> Result<(), Box<dyn std::error::Error>> {
-        self.minimap.update_texture(
-            &image,
-            &mask,
-            &self.display,
-            &self.config.minimap_config.show,
-        )?;
+    fn update(
+        &mut self,
+        image: Mat,
+        mask: Mat,
+        stages: Vec<(String, Mat)>,
+        channels: MaskChannels,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self.operator.as_mut() {
+            Some(operator) => operator.update(&image, &mask, &stages)?,
+            None => self.minimap.update_texture(
+                &image,
+                &mask,
+                &self.display,
+                &self.config.minimap_config.show,
+            )?,
+        }
         self.game.update(&image, &mask, &self.display)?;
+        self.game.update_channels(&channels, &self.display)?;
         Ok(())
     }
 
+    //the pipeline reports which subtractor is active, so a failed switch doesn't leave
+    //the window with the wrong one
+    fn update_pipeline_state(
+        &mut self,
+        subtractor: SubtractorType,
+        parameters: Vec<SubtractorParameter>,
+        parameter_changes: u64,
+    ) {
+        //the index means a different parameter for another subtractor
+        let switched = subtractor != self.config.background_subtractor_config.subtractor_type;
+        if switched {
+            self.selected_parameter = 0;
+        }
+        self.config.background_subtractor_config.subtractor_type = subtractor;
+        //values changed by the pipeline, e.g. clamped ones, are taken once it caught up
+        if switched || parameter_changes >= self.parameter_changes {
+            self.parameters = parameters;
+        }
+    }
+
+    fn switch_subtractor(&mut self) {
+        let selected = next_subtractor(&self.config.background_subtractor_config.subtractor_type);
+        send_pipeline_msg(
+            &self.pipeline_control_queue,
+            PipelineMessage::SetSubtractor(selected),
+        );
+    }
+
+    fn selected_parameter(&mut self) -> Option<&mut SubtractorParameter> {
+        let count = self.parameters.len().max(1);
+        self.parameters.get_mut(self.selected_parameter % count)
+    }
+
+    fn next_parameter(&mut self) {
+        self.selected_parameter += 1;
+        if let Some(parameter) = self.selected_parameter() {
+            println!("selected {} = {}", parameter.name, parameter.value);
+        }
+    }
+
+    //parameters can't become negative
+    fn change_parameter(&mut self, steps: f64) {
+        let Some(parameter) = self.selected_parameter() else {
+            return;
+        };
+        parameter.value = (parameter.value + steps * parameter.step).max(0.0);
+        let msg = PipelineMessage::SetParameter(parameter.name.clone(), parameter.value);
+        send_pipeline_msg(&self.pipeline_control_queue, msg);
+        self.parameter_changes += 1;
+    }
+
+    fn pipeline_status(&self) -> String {
+        let subtractor = &self.config.background_subtractor_config.subtractor_type;
+        match self
+            .parameters
+            .get(self.selected_parameter % self.parameters.len().max(1))
+        {
+            Some(parameter) => format!(
+                "{:?} | {}: {:.3}",
+                subtractor, parameter.name, parameter.value
+            ),
+            None => format!("{:?}", subtractor),
+        }
+    }
+
+    fn reload_shaders(&mut self) {
+        let reload_config = &self.config.shader_reload_config;
+        if !reload_config.enabled
+            || self.last_shader_check.elapsed() < Duration::from_millis(reload_config.interval_ms)
+        {
+            return;
+        }
+        self.last_shader_check = Instant::now();
+
+        self.game.reload_shaders(&self.display);
+        self.minimap.reload_shaders(&self.display);
+        self.post_process.reload_shaders(&self.display);
+        if let Some(operator) = self.operator.as_mut() {
+            operator.reload_shaders();
+        }
+    }
+
+    fn toggle_warp_adjust(&mut self) {
+        if !self.post_process.has_warp_stage() {
+            eprintln!("Warp is not in the post_process_config stages, changes won't be visible");
+        }
+        self.post_process.warp_mut().toggle_adjust();
+    }
+
+    //keys that only work while the warp is adjusted. Returns true if the key was used
+    fn warp_key_event(&mut self, key: &Key) -> bool {
+        let key_config = &self.config.key_config;
+        let warp = self.post_process.warp_mut();
+        match key.as_ref() {
+            Key::Named(NamedKey::ArrowLeft) => warp.move_selected((-1_f32, 0_f32)),
+            Key::Named(NamedKey::ArrowRight) => warp.move_selected((1_f32, 0_f32)),
+            Key::Named(NamedKey::ArrowUp) => warp.move_selected((0_f32, 1_f32)),
+            Key::Named(NamedKey::ArrowDown) => warp.move_selected((0_f32, -1_f32)),
+            Key::Named(NamedKey::Tab) => warp.next_handle(),
+            Key::Character(val) if val.to_lowercase() == key_config.warp_mode_key => {
+                warp.toggle_mode()
+            }
+            Key::Character(val) if val.to_lowercase() == key_config.warp_reset_key => warp.reset(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn uses_pointer(&self) -> bool {
+        matches!(
+            self.config.background_subtractor_config.subtractor_type,
+            SubtractorType::Mouse
+        )
+    }
+
+    fn uses_rendered_frame(&self) -> bool {
+        matches!(
+            self.config.background_subtractor_config.subtractor_type,
+            SubtractorType::Shadow
+        )
+    }
+
+    //what was projected last, for subtractors that compare the camera with the projection
+    fn rendered_frame(&mut self) -> Option<Mat> {
+        if !self.uses_rendered_frame() {
+            return None;
+        }
+        self.post_process.take_readback()
+    }
+
+    fn update_pointer(&mut self, pointer: PointerState) {
+        if pointer == self.pointer {
+            return;
+        }
+        self.pointer = pointer;
+        self.game.pointer_event(&pointer);
+        if self.uses_pointer() {
+            self.pointer_changed = true;
+        }
+    }
+
+    fn take_pointer_update(&mut self) -> Option<PointerState> {
+        match std::mem::take(&mut self.pointer_changed) {
+            true => Some(self.pointer),
+            false => None,
+        }
+    }
+
+    //cursor position in opengl coordinates
+    fn cursor_to_gl(&self, position: (f64, f64)) -> (f32, f32) {
+        let size = self.window.inner_size();
+        (
+            (position.0 / size.width.max(1) as f64 * 2.0 - 1.0) as f32,
+            (1.0 - position.1 / size.height.max(1) as f64 * 2.0) as f32,
+        )
+    }
+
     fn draw(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.timestep.update();
-        let mut frame = self.display.draw();
-        clear_frame(&mut frame);
-        self.game.draw(&mut frame, &self.display, &self.timestep)?;
-        if !matches!(self.config.minimap_config.show, MinimapState::Hide) {
-            self.minimap.draw(&mut frame)?;
+        {
+            let mut target = self.post_process.scene_target(&self.display)?;
+            clear_frame(&mut target);
+            self.game.draw(&mut target, &self.display, &self.timestep)?;
+            //with an operator window the projector only shows the game
+            if self.operator.is_none()
+                && !matches!(self.config.minimap_config.show, MinimapState::Hide)
+            {
+                self.minimap.draw(&mut target)?;
+            }
         }
 
+        let mut frame = self.display.draw();
+        clear_frame(&mut frame);
+        self.post_process.present(&self.display, &mut frame)?;
         frame.finish()?;
+
+        if let Some(operator) = self.operator.as_mut() {
+            let status = format!("{} | {}", self.pipeline_status(), self.game.status());
+            operator.draw(&self.timestep, &status)?;
+        }
         Ok(())
     }
 }
@@ -109,22 +324,85 @@ impl ApplicationHandler for PlatoApp {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
-        if let WindowEvent::KeyboardInput {
-            event: KeyEvent { logical_key, .. },
-            ..
-        } = &event
+        let is_operator = self
+            .operator
+            .as_ref()
+            .is_some_and(|operator| operator.id() == window_id);
+
+        //while the warp is adjusted the keys belong to the warp and not to the game
+        let adjusting_warp = self.post_process.warp().is_adjusting();
+        //games only see presses, held keys would repeat their action
+        if let (
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            },
+            false,
+        ) = (&event, adjusting_warp)
         {
             self.game.key_event(logical_key);
         }
 
         match &event {
+            WindowEvent::CloseRequested if is_operator => {
+                println!("closing operator window");
+                self.operator = None;
+                //the projector stays clean, the minimap can still be shown with its key
+                self.config.minimap_config.show = MinimapState::Hide;
+                send_pipeline_msg(
+                    &self.pipeline_control_queue,
+                    PipelineMessage::CollectStages(false),
+                );
+            }
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::RedrawRequested => {
                 self.window.request_redraw();
             }
+            WindowEvent::Resized(size) if is_operator => {
+                if let Some(operator) = self.operator.as_ref() {
+                    operator.resize((*size).into());
+                }
+            }
+            WindowEvent::Resized(size) => {
+                self.display.resize((*size).into());
+            }
+            WindowEvent::CursorMoved { position, .. } if !is_operator && adjusting_warp => {
+                let pos = self.cursor_to_gl((position.x, position.y));
+                self.post_process.warp_mut().drag(pos);
+                self.cursor = pos;
+            }
+            WindowEvent::CursorMoved { position, .. } if !is_operator => {
+                self.cursor = self.cursor_to_gl((position.x, position.y));
+                self.update_pointer(PointerState {
+                    position: self.cursor,
+                    ..self.pointer
+                });
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } if !is_operator && adjusting_warp => match state {
+                ElementState::Pressed => self.post_process.warp_mut().grab(self.cursor),
+                ElementState::Released => self.post_process.warp_mut().release(),
+            },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } if !is_operator => self.update_pointer(PointerState {
+                pressed: state.is_pressed(),
+                ..self.pointer
+            }),
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -134,6 +412,7 @@ impl ApplicationHandler for PlatoApp {
                     },
                 ..
             } => match key.as_ref() {
+                _ if adjusting_warp && self.warp_key_event(key) => (),
                 Key::Character(val) if val.to_lowercase() == self.config.key_config.quit_key => {
                     send_pipeline_msg(&self.pipeline_control_queue, PipelineMessage::Quit);
                     event_loop.exit();
@@ -148,6 +427,61 @@ impl ApplicationHandler for PlatoApp {
                     self.config.minimap_config.show =
                         rotate_state(&self.config.minimap_config.show);
                 }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.next_monitor_key =>
+                {
+                    move_to_next_monitor(&self.window, &self.config.window_config);
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.next_operator_stage_key =>
+                {
+                    if let Some(operator) = self.operator.as_mut() {
+                        operator.next_stage();
+                    }
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.warp_adjust_key =>
+                {
+                    self.toggle_warp_adjust();
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.toggle_stamp_key =>
+                {
+                    self.update_pointer(PointerState {
+                        stamp: !self.pointer.stamp,
+                        ..self.pointer
+                    });
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.next_subtractor_key =>
+                {
+                    self.switch_subtractor();
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.calibrate_key =>
+                {
+                    send_pipeline_msg(&self.pipeline_control_queue, PipelineMessage::Calibrate);
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.save_model_key =>
+                {
+                    send_pipeline_msg(&self.pipeline_control_queue, PipelineMessage::SaveModel);
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.next_parameter_key =>
+                {
+                    self.next_parameter();
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.increase_parameter_key =>
+                {
+                    self.change_parameter(1.0);
+                }
+                Key::Character(val)
+                    if val.to_lowercase() == self.config.key_config.decrease_parameter_key =>
+                {
+                    self.change_parameter(-1.0);
+                }
                 _ => (),
             },
             _ => (),
@@ -155,7 +489,7 @@ impl ApplicationHandler for PlatoApp {
     }
 }
 
-pub fn clear_frame(frame: &mut glium::Frame) {
+pub fn clear_frame<S: Surface>(frame: &mut S) {
     frame.clear_color(0_f32, 0_f32, 0_f32, 1_f32);
 }
 
@@ -178,17 +512,33 @@ pub fn start_display(
     //handled by Optionals and exceptions, but this way is easier and more natural
     let mut got_image = false;
 
-    //init pipeline, so defaults will be available
-    send_pipeline_msg(&pipeline_control_queue, PipelineMessage::SetReference);
+    //init pipeline, so defaults will be available. The pipeline takes the first image as
+    //reference unless it could load a saved model
+    send_pipeline_msg(
+        &pipeline_control_queue,
+        PipelineMessage::CollectStages(app.operator.is_some()),
+    );
     send_pipeline_msg(&pipeline_control_queue, PipelineMessage::GenerateImage);
     loop {
         match result_queue.try_recv() {
             Ok(result) => {
+                //one rendered frame per camera image is enough and keeps the queue short
+                if let Some(frame) = got_image.then(|| app.rendered_frame()).flatten() {
+                    send_pipeline_msg(
+                        &pipeline_control_queue,
+                        PipelineMessage::RenderedFrame(frame),
+                    );
+                }
                 send_pipeline_msg(&pipeline_control_queue, PipelineMessage::GenerateImage);
+                app.update_pipeline_state(
+                    result.subtractor,
+                    result.parameters,
+                    result.parameter_changes,
+                );
                 match (result.image, result.mask) {
                     (Ok(image), Ok(mask)) => {
                         got_image = true;
-                        app.update(image, mask)?;
+                        app.update(image, mask, result.stages, result.channels)?;
                     }
                     (Err(error), _) => {
                         eprintln!("Window thread reuslt_queue. Received image is error {error}")
@@ -208,11 +558,15 @@ pub fn start_display(
 
         //draw everything and swap buffers
         if got_image {
+            app.reload_shaders();
             app.draw()?;
         }
 
         //handle window events
         let status = event_loop.pump_app_events(Some(Duration::ZERO), &mut app);
+        if let Some(pointer) = app.take_pointer_update() {
+            send_pipeline_msg(&pipeline_control_queue, PipelineMessage::Pointer(pointer));
+        }
 
         //end this whole mess if we're told to do so. This has to be done AFTER
         //all frame stuff happend
@@ -4,9 +4,9 @@ use crate::{
     config::PlatoConfig,
     display::{
         display_window::DisplayType,
-        primitves::{QUAD_INDICES, Vertex, get_quad_buffer},
+        primitves::{QUAD_INDICES, Vertex, get_stream_quad_buffer},
     },
-    game::util::{image_to_gray_texture_r, load_shaders},
+    game::util::{LiveViewMode, StreamTexture, WatchedProgram},
 };
 
 use glium::Surface;
@@ -24,8 +24,9 @@ pub struct BufferCollection {
 
 pub struct Minimap {
     buffers: BufferCollection,
-    texture: glium::Texture2d,
-    program: glium::Program,
+    texture: StreamTexture,
+    program: WatchedProgram,
+    live_view_mode: LiveViewMode,
 }
 
 #[derive(Deserialize, Clone)]
@@ -45,18 +46,34 @@ pub fn rotate_state(state: &MinimapState) -> MinimapState {
 
 impl Minimap {
     pub fn new(display: &DisplayType, config: &PlatoConfig) -> Result<Minimap, Box<dyn Error>> {
-        let default_texture = image_to_gray_texture_r(display, &get_empty_minimap())?;
+        Minimap::with_layout(
+            display,
+            config.minimap_config.position,
+            config.minimap_config.dims,
+            config.minimap_config.live_view_mode,
+        )
+    }
+
+    pub fn with_layout(
+        display: &DisplayType,
+        position: (f32, f32),
+        dims: (f32, f32),
+        live_view_mode: LiveViewMode,
+    ) -> Result<Minimap, Box<dyn Error>> {
+        let mut default_texture = StreamTexture::new();
+        default_texture.upload_gray(display, &get_empty_minimap())?;
         Ok(Minimap {
-            buffers: get_buffers(
-                display,
-                config.minimap_config.position,
-                config.minimap_config.dims,
-            )?,
+            buffers: get_buffers(display, position, dims)?,
             texture: default_texture,
-            program: load_shaders("src/shaders/minimap.toml", display)?,
+            program: WatchedProgram::load("src/shaders/minimap.toml", display)?,
+            live_view_mode,
         })
     }
-    pub fn draw(&self, frame: &mut glium::Frame) -> Result<(), Box<dyn Error>> {
+    pub fn reload_shaders(&mut self, display: &DisplayType) {
+        self.program.reload_if_changed(display);
+    }
+
+    pub fn draw<S: Surface>(&self, frame: &mut S) -> Result<(), Box<dyn Error>> {
         let params = glium::DrawParameters {
             blend: glium::draw_parameters::Blend::alpha_blending(),
             ..Default::default()
@@ -65,7 +82,8 @@ impl Minimap {
             &self.buffers.vertex_buffer,
             &self.buffers.index_buffer,
             &self.program,
-            &uniform! { tex: &self.texture },
+            &uniform! { tex: self.texture.texture().ok_or("no minimap texture")?,
+            color_tex: self.texture.is_color() },
             &params,
         )?;
         Ok(())
@@ -83,9 +101,11 @@ impl Minimap {
             MinimapState::ShowMask => mask,
             MinimapState::ShowImage => image,
         };
-        let texture = image_to_gray_texture_r(display, data)?;
-        self.texture = texture;
-        Ok(())
+        self.set_texture(display, data)
+    }
+
+    pub fn set_texture(&mut self, display: &DisplayType, data: &Mat) -> Result<(), Box<dyn Error>> {
+        self.texture.upload(display, data, self.live_view_mode)
     }
 }
 
@@ -98,7 +118,7 @@ fn get_buffers(
     pos: (f32, f32),
     dims: (f32, f32),
 ) -> Result<BufferCollection, Box<dyn Error>> {
-    let verticies = get_quad_buffer((pos.0, pos.0 + dims.0), (pos.1, pos.1 + dims.1));
+    let verticies = get_stream_quad_buffer((pos.0, pos.0 + dims.0), (pos.1, pos.1 + dims.1));
     let vertex_buffer = glium::VertexBuffer::new(display, &verticies)?;
     let index_buffer = glium::IndexBuffer::new(
         display,
@@ -1,6 +1,11 @@
 pub mod display_window;
 pub mod minimap;
+pub mod operator;
+pub mod post_process;
 pub mod primitves;
+pub mod sprite;
 pub mod timestep;
+pub mod warp;
+pub mod window;
 
 pub use display_window::start_display;
@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use crate::config::PlatoConfig;
+use crate::display::display_window::{DisplayType, clear_frame};
+use crate::display::minimap::Minimap;
+use crate::display::timestep::TimeStep;
+use crate::display::window::build_window;
+use crate::game::util::LiveViewMode;
+
+use glium::winit::event_loop::EventLoop;
+use glium::winit::window::{Window, WindowId};
+
+use opencv::core::{CV_8U, NORM_MINMAX, no_array, normalize};
+use opencv::prelude::*;
+
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+//second window for the operator. It shows the camera image, the mask and one
+//intermediate stage of the pipeline, so the projector only has to show the game
+pub struct OperatorWindow {
+    window: Window,
+    display: DisplayType,
+    image_tile: Minimap,
+    mask_tile: Minimap,
+    stage_tile: Minimap,
+    stage_names: Vec<String>,
+    selected_stage: usize,
+    last_title_update: Instant,
+}
+
+fn stage_to_displayable(stage: &Mat) -> Result<Mat, Box<dyn Error>> {
+    if stage.depth() == CV_8U {
+        return Ok(stage.clone());
+    }
+    let mut res = Mat::default();
+    normalize(stage, &mut res, 0.0, 255.0, NORM_MINMAX, CV_8U, &no_array())?;
+    Ok(res)
+}
+
+impl OperatorWindow {
+    pub fn new(
+        event_loop: &EventLoop<()>,
+        config: &PlatoConfig,
+    ) -> Result<OperatorWindow, Box<dyn Error>> {
+        let (window, display) = build_window(
+            event_loop,
+            "Platos Beamer operator",
+            &config.operator_config.window_config,
+        )?;
+        let live_view_mode = config.minimap_config.live_view_mode;
+        Ok(OperatorWindow {
+            image_tile: Minimap::with_layout(&display, (-1.0, 0.0), (1.0, 1.0), live_view_mode)?,
+            mask_tile: Minimap::with_layout(&display, (0.0, 0.0), (1.0, 1.0), LiveViewMode::Gray)?,
+            stage_tile: Minimap::with_layout(
+                &display,
+                (-1.0, -1.0),
+                (1.0, 1.0),
+                LiveViewMode::Gray,
+            )?,
+            window,
+            display,
+            stage_names: Vec::new(),
+            selected_stage: 0,
+            last_title_update: Instant::now() - TITLE_UPDATE_INTERVAL,
+        })
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn resize(&self, size: (u32, u32)) {
+        self.display.resize(size);
+    }
+
+    pub fn reload_shaders(&mut self) {
+        self.image_tile.reload_shaders(&self.display);
+        self.mask_tile.reload_shaders(&self.display);
+        self.stage_tile.reload_shaders(&self.display);
+    }
+
+    pub fn next_stage(&mut self) {
+        self.selected_stage += 1;
+    }
+
+    pub fn update(
+        &mut self,
+        image: &Mat,
+        mask: &Mat,
+        stages: &[(String, Mat)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.image_tile.set_texture(&self.display, image)?;
+        self.mask_tile.set_texture(&self.display, mask)?;
+
+        self.stage_names = stages.iter().map(|(name, _)| name.clone()).collect();
+        if let Some((_, stage)) = stages.get(self.selected_stage % stages.len().max(1)) {
+            self.stage_tile
+                .set_texture(&self.display, &stage_to_displayable(stage)?)?;
+        }
+        Ok(())
+    }
+
+    fn update_title(&mut self, timestep: &TimeStep, game_status: &str) {
+        if self.last_title_update.elapsed() < TITLE_UPDATE_INTERVAL {
+            return;
+        }
+        self.last_title_update = Instant::now();
+
+        let fps = match timestep.averager.get_fps() {
+            Some(fps) => format!("{:.1} fps", fps),
+            None => "- fps".to_string(),
+        };
+        let stage = self
+            .stage_names
+            .get(self.selected_stage % self.stage_names.len().max(1))
+            .map(|name| name.as_str())
+            .unwrap_or("no stages");
+        self.window.set_title(&format!(
+            "Platos Beamer operator | {} | stage: {} | {}",
+            fps, stage, game_status
+        ));
+    }
+
+    pub fn draw(&mut self, timestep: &TimeStep, game_status: &str) -> Result<(), Box<dyn Error>> {
+        self.update_title(timestep, game_status);
+
+        let mut frame = self.display.draw();
+        clear_frame(&mut frame);
+        self.image_tile.draw(&mut frame)?;
+        self.mask_tile.draw(&mut frame)?;
+        self.stage_tile.draw(&mut frame)?;
+        frame.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use std::error::Error;
+
+use crate::config::{PostProcessConfig, WarpConfig};
+use crate::display::display_window::DisplayType;
+use crate::display::primitves::{QUAD_INDICES, Vertex, get_quad_buffer};
+use crate::display::warp::Warp;
+use crate::game::util::WatchedProgram;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction, Uniforms,
+};
+use glium::{Surface, uniform};
+
+use opencv::core::Mat;
+use opencv::prelude::*;
+use serde::Deserialize;
+
+//stages of the post processing chain. They are applied in the order of the config
+#[derive(Deserialize, Clone, Debug)]
+pub enum PostStage {
+    //adds a blurred copy of everything brighter than threshold
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    ColorGrading {
+        brightness: f32,
+        contrast: f32,
+        saturation: f32,
+        tint: [f32; 3],
+    },
+    //projectors are darker at the edges. This brightens everything outside of radius,
+    //radius is relative to the distance from the center to the edge
+    Vignette {
+        strength: f32,
+        radius: f32,
+    },
+    Gamma {
+        gamma: f32,
+    },
+    //maps the image onto the projection surface with the keystone or mesh warp, see Warp
+    Warp,
+}
+
+//size of the scene copies handed to the pipeline. Small, reading back stalls the renderer
+const READBACK_SIZE: (u32, u32) = (320, 240);
+
+fn get_stage_shader(stage: &PostStage) -> &'static str {
+    match stage {
+        PostStage::Bloom { .. } => "src/shaders/post_bloom.toml",
+        PostStage::ColorGrading { .. } => "src/shaders/post_color_grading.toml",
+        PostStage::Vignette { .. } => "src/shaders/post_vignette.toml",
+        PostStage::Gamma { .. } => "src/shaders/post_gamma.toml",
+        PostStage::Warp => "src/shaders/post_warp.toml",
+    }
+}
+
+fn create_target_texture(
+    display: &DisplayType,
+    (width, height): (u32, u32),
+) -> Result<Texture2d, Box<dyn Error>> {
+    Ok(Texture2d::empty_with_format(
+        display,
+        UncompressedFloatFormat::F16F16F16F16,
+        MipmapsOption::NoMipmap,
+        width.max(1),
+        height.max(1),
+    )?)
+}
+
+fn sample(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+    texture
+        .sampled()
+        .minify_filter(MinifySamplerFilter::Linear)
+        .magnify_filter(MagnifySamplerFilter::Linear)
+        .wrap_function(SamplerWrapFunction::Clamp)
+}
+
+//Games are drawn into an offscreen scene texture. Presenting runs the scene through the
+//configured stages, ping-ponging between two textures, and the last stage draws into the window
+pub struct PostProcessor {
+    scene: Texture2d,
+    ping: Texture2d,
+    pong: Texture2d,
+    readback: Texture2d,
+    readback_requested: bool,
+    readback_frame: Option<Mat>,
+    vertex_buffer: glium::VertexBuffer<Vertex>,
+    index_buffer: glium::IndexBuffer<u16>,
+    copy_program: WatchedProgram,
+    stages: Vec<(PostStage, WatchedProgram)>,
+    warp: Warp,
+}
+
+impl PostProcessor {
+    pub fn new(
+        display: &DisplayType,
+        config: &PostProcessConfig,
+        warp_config: &WarpConfig,
+    ) -> Result<PostProcessor, Box<dyn Error>> {
+        let dims = display.get_framebuffer_dimensions();
+        let verticies = get_quad_buffer((-1_f32, 1_f32), (-1_f32, 1_f32));
+        let mut stages = Vec::with_capacity(config.stages.len());
+        for stage in config.stages.iter() {
+            let program = WatchedProgram::load(get_stage_shader(stage), display)?;
+            stages.push((stage.clone(), program));
+        }
+        Ok(PostProcessor {
+            scene: create_target_texture(display, dims)?,
+            ping: create_target_texture(display, dims)?,
+            pong: create_target_texture(display, dims)?,
+            readback: create_target_texture(display, READBACK_SIZE)?,
+            readback_requested: false,
+            readback_frame: None,
+            vertex_buffer: glium::VertexBuffer::new(display, &verticies)?,
+            index_buffer: glium::IndexBuffer::new(
+                display,
+                glium::index::PrimitiveType::TrianglesList,
+                &QUAD_INDICES,
+            )?,
+            copy_program: WatchedProgram::load("src/shaders/post_copy.toml", display)?,
+            stages,
+            warp: Warp::new(display, warp_config)?,
+        })
+    }
+
+    //the offscreen textures follow the window size
+    fn resize_if_needed(&mut self, display: &DisplayType) -> Result<(), Box<dyn Error>> {
+        let dims = display.get_framebuffer_dimensions();
+        if self.scene.dimensions() != dims {
+            self.scene = create_target_texture(display, dims)?;
+            self.ping = create_target_texture(display, dims)?;
+            self.pong = create_target_texture(display, dims)?;
+        }
+        Ok(())
+    }
+
+    //target the games draw into
+    pub fn scene_target(
+        &mut self,
+        display: &DisplayType,
+    ) -> Result<SimpleFrameBuffer<'_>, Box<dyn Error>> {
+        self.resize_if_needed(display)?;
+        Ok(SimpleFrameBuffer::new(display, &self.scene)?)
+    }
+
+    pub fn reload_shaders(&mut self, display: &DisplayType) {
+        self.copy_program.reload_if_changed(display);
+        for (_, program) in self.stages.iter_mut() {
+            program.reload_if_changed(display);
+        }
+        self.warp.reload_shaders(display);
+    }
+
+    pub fn warp(&self) -> &Warp {
+        &self.warp
+    }
+
+    pub fn warp_mut(&mut self) -> &mut Warp {
+        &mut self.warp
+    }
+
+    pub fn has_warp_stage(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|(stage, _)| matches!(stage, PostStage::Warp))
+    }
+
+    //downscaled RGBA copy of what the last present drew, after the warp and all stages, so it
+    //matches what the projector shows. Every call asks for the next present to be read back.
+    //Rows are in opengl order, so the bottom row comes first
+    pub fn take_readback(&mut self) -> Option<Mat> {
+        self.readback_requested = true;
+        self.readback_frame.take()
+    }
+
+    fn read_output(
+        &self,
+        display: &DisplayType,
+        source: &Texture2d,
+    ) -> Result<Mat, Box<dyn Error>> {
+        let mut target = SimpleFrameBuffer::new(display, &self.readback)?;
+        self.final_pass(source, &mut target)?;
+        let image: RawImage2d<u8> = self.readback.read();
+        let mat = Mat::from_slice(&image.data)?
+            .reshape(4, image.height as i32)?
+            .try_clone()?;
+        Ok(mat)
+    }
+
+    fn draw_pass<S: Surface, U: Uniforms>(
+        &self,
+        target: &mut S,
+        program: &glium::Program,
+        uniforms: &U,
+    ) -> Result<(), Box<dyn Error>> {
+        target.draw(
+            &self.vertex_buffer,
+            &self.index_buffer,
+            program,
+            uniforms,
+            &glium::DrawParameters::default(),
+        )?;
+        Ok(())
+    }
+
+    fn apply_stage<S: Surface>(
+        &self,
+        stage: &PostStage,
+        program: &glium::Program,
+        source: &Texture2d,
+        target: &mut S,
+    ) -> Result<(), Box<dyn Error>> {
+        let tex = sample(source);
+        match stage {
+            PostStage::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => {
+                let (width, height) = source.dimensions();
+                let texel = [1_f32 / width as f32, 1_f32 / height as f32];
+                self.draw_pass(
+                    target,
+                    program,
+                    &uniform! {tex: tex, texel: texel, threshold: *threshold,
+                    intensity: *intensity, radius: *radius},
+                )
+            }
+            PostStage::ColorGrading {
+                brightness,
+                contrast,
+                saturation,
+                tint,
+            } => self.draw_pass(
+                target,
+                program,
+                &uniform! {tex: tex, brightness: *brightness, contrast: *contrast,
+                saturation: *saturation, tint: *tint},
+            ),
+            PostStage::Vignette { strength, radius } => self.draw_pass(
+                target,
+                program,
+                &uniform! {tex: tex, strength: *strength, radius: *radius},
+            ),
+            PostStage::Gamma { gamma } => {
+                self.draw_pass(target, program, &uniform! {tex: tex, gamma: *gamma})
+            }
+            PostStage::Warp => self.warp.draw(target, program, tex),
+        }
+    }
+
+    //all stages but the last one, returns the texture holding their result
+    fn run_stages(&self, display: &DisplayType) -> Result<&Texture2d, Box<dyn Error>> {
+        let mut source = &self.scene;
+        let stages = self
+            .stages
+            .split_last()
+            .map_or(&[][..], |(_, stages)| stages);
+        for (i, (stage, program)) in stages.iter().enumerate() {
+            let texture = if i % 2 == 0 { &self.ping } else { &self.pong };
+            let mut target = SimpleFrameBuffer::new(display, texture)?;
+            self.apply_stage(stage, program, source, &mut target)?;
+            source = texture;
+        }
+        Ok(source)
+    }
+
+    //the last stage, or a plain copy without stages
+    fn final_pass<S: Surface>(
+        &self,
+        source: &Texture2d,
+        target: &mut S,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.stages.last() {
+            Some((stage, program)) => self.apply_stage(stage, program, source, target),
+            None => self.draw_pass(target, &self.copy_program, &uniform! {tex: sample(source)}),
+        }
+    }
+
+    //runs the chain on the scene and draws the result into frame
+    pub fn present(
+        &mut self,
+        display: &DisplayType,
+        frame: &mut glium::Frame,
+    ) -> Result<(), Box<dyn Error>> {
+        self.warp.update(display)?;
+        let source = self.run_stages(display)?;
+        self.final_pass(source, frame)?;
+        if self.readback_requested {
+            self.readback_frame = Some(self.read_output(display, source)?);
+            self.readback_requested = false;
+        }
+        self.warp.draw_handles(frame)
+    }
+}
@@ -3,8 +3,8 @@ use glium::implement_vertex;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
-    position: [f32; 2],
-    uv: [f32; 2],
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
 }
 
 implement_vertex!(Vertex, position, uv);
@@ -30,4 +30,14 @@ pub fn get_quad_buffer(x_size: (f32, f32), y_size: (f32, f32)) -> [Vertex; 4] {
     ]
 }
 
+//quad for textures uploaded by StreamTexture. Camera images are stored top row first,
+//flipping both uv axes turns them upright and mirrors them for the projection
+pub fn get_stream_quad_buffer(x_size: (f32, f32), y_size: (f32, f32)) -> [Vertex; 4] {
+    let mut quad = get_quad_buffer(x_size, y_size);
+    for vertex in quad.iter_mut() {
+        vertex.uv = [1.0 - vertex.uv[0], 1.0 - vertex.uv[1]];
+    }
+    quad
+}
+
 pub const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
@@ -0,0 +1,119 @@
+use crate::display::display_window::DisplayType;
+
+use glium::index::{NoIndices, PrimitiveType};
+use glium::uniforms::Uniforms;
+use glium::{DrawParameters, Surface, VertexBuffer, implement_vertex};
+
+//corner of the unit quad, every sprite is drawn as an instance of it
+#[derive(Copy, Clone, Debug)]
+struct SpriteCorner {
+    corner: [f32; 2],
+}
+implement_vertex!(SpriteCorner, corner);
+
+//per instance data. scale is the full width and height of the sprite, so a sprite with
+//scale [2.0, 2.0] covers the whole screen. What color and texture_layer mean is up to the shader
+#[derive(Copy, Clone, Debug)]
+pub struct SpriteInstance {
+    pub center: [f32; 2],
+    pub scale: [f32; 2],
+    pub rotation: f32,
+    pub color: [f32; 4],
+    pub texture_layer: f32,
+}
+implement_vertex!(
+    SpriteInstance,
+    center,
+    scale,
+    rotation,
+    color,
+    texture_layer
+);
+
+//triangle strip going top left, top right, bottom left, bottom right
+const SPRITE_CORNERS: [SpriteCorner; 4] = [
+    SpriteCorner {
+        corner: [-1.0, 1.0],
+    },
+    SpriteCorner { corner: [1.0, 1.0] },
+    SpriteCorner {
+        corner: [-1.0, -1.0],
+    },
+    SpriteCorner {
+        corner: [1.0, -1.0],
+    },
+];
+
+//Draws a list of sprites in a single instanced draw call. The instance buffer is kept
+//between frames and only reallocated if it gets too small, so filling it every frame
+//doesn't allocate on the gpu
+pub struct SpriteRenderer {
+    quad: VertexBuffer<SpriteCorner>,
+    instance_buffer: VertexBuffer<SpriteInstance>,
+    instances: Vec<SpriteInstance>,
+    uploaded: usize,
+}
+
+impl SpriteRenderer {
+    pub fn new(
+        display: &DisplayType,
+        capacity: usize,
+    ) -> Result<SpriteRenderer, Box<dyn std::error::Error>> {
+        Ok(SpriteRenderer {
+            quad: VertexBuffer::new(display, &SPRITE_CORNERS)?,
+            instance_buffer: VertexBuffer::empty_dynamic(display, capacity.max(1))?,
+            instances: Vec::with_capacity(capacity),
+            uploaded: 0,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    pub fn push(&mut self, instance: SpriteInstance) {
+        self.instances.push(instance);
+    }
+
+    //writes the pushed sprites to the gpu. The buffer only ever grows
+    pub fn upload(&mut self, display: &DisplayType) -> Result<(), Box<dyn std::error::Error>> {
+        let count = self.instances.len();
+        if count > self.instance_buffer.len() {
+            let capacity = count.max(2 * self.instance_buffer.len());
+            self.instance_buffer = VertexBuffer::empty_dynamic(display, capacity)?;
+        }
+        if count > 0 {
+            self.instance_buffer
+                .slice_mut(0..count)
+                .ok_or("sprite buffer too small")?
+                .write(&self.instances);
+        }
+        self.uploaded = count;
+        Ok(())
+    }
+
+    pub fn draw<S: Surface, U: Uniforms>(
+        &self,
+        target: &mut S,
+        program: &glium::Program,
+        uniforms: &U,
+        params: &DrawParameters,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        //drawing zero instances isn't allowed
+        if self.uploaded == 0 {
+            return Ok(());
+        }
+        let instances = self
+            .instance_buffer
+            .slice(0..self.uploaded)
+            .ok_or("sprite buffer too small")?;
+        target.draw(
+            (&self.quad, instances.per_instance()?),
+            NoIndices(PrimitiveType::TriangleStrip),
+            program,
+            uniforms,
+            params,
+        )?;
+        Ok(())
+    }
+}
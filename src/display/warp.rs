@@ -0,0 +1,437 @@
+use std::error::Error;
+
+use crate::config::{WarpConfig, load_config};
+use crate::display::display_window::DisplayType;
+use crate::display::primitves::Vertex;
+use crate::game::util::WatchedProgram;
+
+use glium::Texture2d;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::uniforms::Sampler;
+use glium::{DrawParameters, IndexBuffer, Surface, VertexBuffer, implement_vertex, uniform};
+
+use serde::{Deserialize, Serialize};
+
+//number of quads per axis the warped image is tesselated into
+const WARP_RESOLUTION: usize = 32;
+//handles further away from the cursor than this can't be grabbed
+const GRAB_DISTANCE: f32 = 0.1;
+//grid lines shown in keystone mode while adjusting
+const KEYSTONE_GRID: (f32, f32) = (8.0, 8.0);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum WarpMode {
+    //the image is mapped onto the quad spanned by four corners
+    Keystone,
+    //the image is mapped onto a grid of control points
+    Mesh,
+}
+
+//what gets persisted in the warp file. Positions are in opengl coordinates.
+//corners are bottom left, bottom right, top right, top left.
+//mesh is stored row by row starting at the bottom, mesh_size is (columns, rows)
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WarpSettings {
+    pub mode: WarpMode,
+    pub corners: [[f32; 2]; 4],
+    pub mesh_size: (usize, usize),
+    pub mesh: Vec<[f32; 2]>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Handle {
+    position: [f32; 2],
+    selected: f32,
+}
+implement_vertex!(Handle, position, selected);
+
+const IDENTITY_CORNERS: [[f32; 2]; 4] = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+
+//projective mapping of the unit square onto the quad spanned by corners
+struct Homography {
+    x: [f32; 3],
+    y: [f32; 3],
+    w: [f32; 2],
+}
+
+impl Homography {
+    fn from_corners(corners: &[[f32; 2]; 4]) -> Homography {
+        let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = *corners;
+        let sx = x0 - x1 + x2 - x3;
+        let sy = y0 - y1 + y2 - y3;
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let det = dx1 * dy2 - dx2 * dy1;
+        //a degenerate quad falls back to an affine mapping
+        let (g, h) = match det.abs() > f32::EPSILON {
+            true => ((sx * dy2 - dx2 * sy) / det, (dx1 * sy - sx * dy1) / det),
+            false => (0_f32, 0_f32),
+        };
+        Homography {
+            x: [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+            y: [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+            w: [g, h],
+        }
+    }
+
+    fn apply(&self, (u, v): (f32, f32)) -> [f32; 2] {
+        let w = self.w[0] * u + self.w[1] * v + 1_f32;
+        [
+            (self.x[0] * u + self.x[1] * v + self.x[2]) / w,
+            (self.y[0] * u + self.y[1] * v + self.y[2]) / w,
+        ]
+    }
+}
+
+fn create_mesh(corners: &[[f32; 2]; 4], (cols, rows): (usize, usize)) -> Vec<[f32; 2]> {
+    let homography = Homography::from_corners(corners);
+    (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (col, row)))
+        .map(|(col, row)| {
+            homography.apply((
+                col as f32 / (cols - 1) as f32,
+                row as f32 / (rows - 1) as f32,
+            ))
+        })
+        .collect()
+}
+
+//bilinear interpolation between the control points of the cell uv lies in
+fn sample_mesh(mesh: &[[f32; 2]], (cols, rows): (usize, usize), (u, v): (f32, f32)) -> [f32; 2] {
+    let fx = u * (cols - 1) as f32;
+    let fy = v * (rows - 1) as f32;
+    let col = (fx.floor() as usize).min(cols - 2);
+    let row = (fy.floor() as usize).min(rows - 2);
+    let (tx, ty) = (fx - col as f32, fy - row as f32);
+    let point = |c: usize, r: usize| mesh[r * cols + c];
+    let (p00, p10) = (point(col, row), point(col + 1, row));
+    let (p01, p11) = (point(col, row + 1), point(col + 1, row + 1));
+    let mut res = [0_f32; 2];
+    for (i, value) in res.iter_mut().enumerate() {
+        let bottom = p00[i] * (1_f32 - tx) + p10[i] * tx;
+        let top = p01[i] * (1_f32 - tx) + p11[i] * tx;
+        *value = bottom * (1_f32 - ty) + top * ty;
+    }
+    res
+}
+
+impl WarpSettings {
+    fn identity(mesh_size: (usize, usize)) -> WarpSettings {
+        WarpSettings {
+            mode: WarpMode::Keystone,
+            corners: IDENTITY_CORNERS,
+            mesh_size,
+            mesh: create_mesh(&IDENTITY_CORNERS, mesh_size),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let (cols, rows) = self.mesh_size;
+        if cols < 2 || rows < 2 {
+            return Err("warp mesh needs at least 2x2 control points".into());
+        }
+        if self.mesh.len() != cols * rows {
+            return Err(format!(
+                "warp mesh has {} control points, but mesh_size is {}x{}",
+                self.mesh.len(),
+                cols,
+                rows
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn position(&self, uv: (f32, f32)) -> [f32; 2] {
+        match self.mode {
+            WarpMode::Keystone => Homography::from_corners(&self.corners).apply(uv),
+            WarpMode::Mesh => sample_mesh(&self.mesh, self.mesh_size, uv),
+        }
+    }
+
+    fn handles(&self) -> &[[f32; 2]] {
+        match self.mode {
+            WarpMode::Keystone => &self.corners,
+            WarpMode::Mesh => &self.mesh,
+        }
+    }
+
+    fn handles_mut(&mut self) -> &mut [[f32; 2]] {
+        match self.mode {
+            WarpMode::Keystone => &mut self.corners,
+            WarpMode::Mesh => &mut self.mesh,
+        }
+    }
+}
+
+fn load_warp_settings(config: &WarpConfig) -> WarpSettings {
+    if !std::path::Path::new(&config.file).exists() {
+        println!("no warp file at {}, starting without warp", config.file);
+        return WarpSettings::identity(config.mesh_size);
+    }
+    let settings = load_config::<WarpSettings>(&config.file)
+        .and_then(|settings| settings.validate().map(|_| settings));
+    match settings {
+        Ok(settings) => {
+            println!("loaded warp from {}", config.file);
+            settings
+        }
+        Err(err) => {
+            eprintln!(
+                "could not load warp file {}, starting without warp: {}",
+                config.file, err
+            );
+            WarpSettings::identity(config.mesh_size)
+        }
+    }
+}
+
+fn create_grid_indices() -> Vec<u16> {
+    let stride = WARP_RESOLUTION + 1;
+    (0..WARP_RESOLUTION)
+        .flat_map(|row| (0..WARP_RESOLUTION).map(move |col| (col, row)))
+        .flat_map(|(col, row)| {
+            let i = (row * stride + col) as u16;
+            let stride = stride as u16;
+            [i, i + 1, i + stride + 1, i, i + stride + 1, i + stride]
+        })
+        .collect()
+}
+
+//Geometric correction of the output. The image is drawn onto a tesselated grid whose
+//vertices are moved by the keystone or mesh warp. While adjusting, the control points
+//are shown as handles and can be moved with the keyboard or the mouse
+pub struct Warp {
+    settings: WarpSettings,
+    config: WarpConfig,
+    vertex_buffer: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer<u16>,
+    handle_program: WatchedProgram,
+    //only exists while adjusting, rewritten when a handle or the selection changes
+    handle_buffer: Option<VertexBuffer<Handle>>,
+    dirty: bool,
+    handles_dirty: bool,
+    adjusting: bool,
+    selected: usize,
+    dragging: bool,
+}
+
+impl Warp {
+    pub fn new(display: &DisplayType, config: &WarpConfig) -> Result<Warp, Box<dyn Error>> {
+        let stride = WARP_RESOLUTION + 1;
+        Ok(Warp {
+            settings: load_warp_settings(config),
+            config: config.clone(),
+            vertex_buffer: VertexBuffer::empty_dynamic(display, stride * stride)?,
+            index_buffer: IndexBuffer::new(
+                display,
+                PrimitiveType::TrianglesList,
+                &create_grid_indices(),
+            )?,
+            handle_program: WatchedProgram::load("src/shaders/post_warp_handles.toml", display)?,
+            handle_buffer: None,
+            dirty: true,
+            handles_dirty: true,
+            adjusting: false,
+            selected: 0,
+            dragging: false,
+        })
+    }
+
+    pub fn reload_shaders(&mut self, display: &DisplayType) {
+        self.handle_program.reload_if_changed(display);
+    }
+
+    pub fn is_adjusting(&self) -> bool {
+        self.adjusting
+    }
+
+    //leaving the adjust mode saves the warp
+    pub fn toggle_adjust(&mut self) {
+        self.adjusting = !self.adjusting;
+        self.dragging = false;
+        match self.adjusting {
+            true => println!("adjusting {:?} warp", self.settings.mode),
+            false => self.save(),
+        }
+    }
+
+    pub fn save(&self) {
+        let res = toml::to_string(&self.settings)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                std::fs::write(&self.config.file, contents).map_err(|err| err.to_string())
+            });
+        match res {
+            Ok(()) => println!("saved warp to {}", self.config.file),
+            Err(err) => eprintln!("could not save warp to {}: {}", self.config.file, err),
+        }
+    }
+
+    //switching to mesh mode starts from the current keystone if the mesh size changed
+    pub fn toggle_mode(&mut self) {
+        self.settings.mode = match self.settings.mode {
+            WarpMode::Keystone => {
+                if self.settings.mesh_size != self.config.mesh_size {
+                    self.settings.mesh_size = self.config.mesh_size;
+                    self.settings.mesh = create_mesh(&self.settings.corners, self.config.mesh_size);
+                }
+                WarpMode::Mesh
+            }
+            WarpMode::Mesh => WarpMode::Keystone,
+        };
+        self.selected = 0;
+        self.dirty = true;
+        self.handles_dirty = true;
+        println!("adjusting {:?} warp", self.settings.mode);
+    }
+
+    //keystone resets to the full screen, the mesh is reset to the current keystone
+    pub fn reset(&mut self) {
+        match self.settings.mode {
+            WarpMode::Keystone => self.settings.corners = IDENTITY_CORNERS,
+            WarpMode::Mesh => {
+                self.settings.mesh_size = self.config.mesh_size;
+                self.settings.mesh = create_mesh(&self.settings.corners, self.config.mesh_size);
+            }
+        }
+        self.selected = 0;
+        self.dirty = true;
+        self.handles_dirty = true;
+    }
+
+    pub fn next_handle(&mut self) {
+        self.selected = (self.selected + 1) % self.settings.handles().len();
+        self.handles_dirty = true;
+    }
+
+    //moves the selected handle by the given number of steps
+    pub fn move_selected(&mut self, (dx, dy): (f32, f32)) {
+        let step = self.config.step;
+        let handle = &mut self.settings.handles_mut()[self.selected];
+        handle[0] += dx * step;
+        handle[1] += dy * step;
+        self.dirty = true;
+        self.handles_dirty = true;
+    }
+
+    //grabs the handle closest to pos, pos is in opengl coordinates
+    pub fn grab(&mut self, pos: (f32, f32)) {
+        let closest = self
+            .settings
+            .handles()
+            .iter()
+            .map(|handle| ((handle[0] - pos.0).powi(2) + (handle[1] - pos.1).powi(2)).sqrt())
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((index, _)) = closest.filter(|(_, distance)| *distance < GRAB_DISTANCE) {
+            self.selected = index;
+            self.dragging = true;
+            self.handles_dirty = true;
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.dragging = false;
+    }
+
+    pub fn drag(&mut self, pos: (f32, f32)) {
+        if self.dragging {
+            self.settings.handles_mut()[self.selected] = [pos.0, pos.1];
+            self.dirty = true;
+            self.handles_dirty = true;
+        }
+    }
+
+    //rebuilds the tesselated grid and the handles after the warp changed
+    pub fn update(&mut self, display: &DisplayType) -> Result<(), Box<dyn Error>> {
+        if self.adjusting && self.handles_dirty {
+            self.update_handles(display)?;
+        }
+        if !self.dirty {
+            return Ok(());
+        }
+        let stride = WARP_RESOLUTION + 1;
+        let vertices: Vec<Vertex> = (0..stride)
+            .flat_map(|row| (0..stride).map(move |col| (col, row)))
+            .map(|(col, row)| {
+                let uv = (
+                    col as f32 / WARP_RESOLUTION as f32,
+                    row as f32 / WARP_RESOLUTION as f32,
+                );
+                Vertex {
+                    position: self.settings.position(uv),
+                    uv: [uv.0, uv.1],
+                }
+            })
+            .collect();
+        self.vertex_buffer.write(&vertices);
+        self.dirty = false;
+        Ok(())
+    }
+
+    //the buffer is only recreated when the number of handles changes with the mode
+    fn update_handles(&mut self, display: &DisplayType) -> Result<(), Box<dyn Error>> {
+        let handles: Vec<Handle> = self
+            .settings
+            .handles()
+            .iter()
+            .enumerate()
+            .map(|(i, position)| Handle {
+                position: *position,
+                selected: if i == self.selected { 1_f32 } else { 0_f32 },
+            })
+            .collect();
+        match self.handle_buffer.as_mut() {
+            Some(buffer) if buffer.len() == handles.len() => buffer.write(&handles),
+            _ => self.handle_buffer = Some(VertexBuffer::dynamic(display, &handles)?),
+        }
+        self.handles_dirty = false;
+        Ok(())
+    }
+
+    //number of grid lines the warp shader draws while adjusting
+    fn grid_cells(&self) -> (f32, f32) {
+        match self.settings.mode {
+            WarpMode::Keystone => KEYSTONE_GRID,
+            WarpMode::Mesh => (
+                (self.settings.mesh_size.0 - 1) as f32,
+                (self.settings.mesh_size.1 - 1) as f32,
+            ),
+        }
+    }
+
+    //draws the warped image. While adjusting the shader overlays a grid
+    pub fn draw<S: Surface>(
+        &self,
+        target: &mut S,
+        program: &glium::Program,
+        tex: Sampler<'_, Texture2d>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (cols, rows) = self.grid_cells();
+        target.draw(
+            &self.vertex_buffer,
+            &self.index_buffer,
+            program,
+            &uniform! {tex: tex, show_grid: self.adjusting, grid_cells: [cols, rows]},
+            &DrawParameters::default(),
+        )?;
+        Ok(())
+    }
+
+    pub fn draw_handles<S: Surface>(&self, target: &mut S) -> Result<(), Box<dyn Error>> {
+        let Some(handle_buffer) = self.handle_buffer.as_ref().filter(|_| self.adjusting) else {
+            return Ok(());
+        };
+        target.draw(
+            handle_buffer,
+            NoIndices(PrimitiveType::Points),
+            &self.handle_program,
+            &uniform! {},
+            &DrawParameters {
+                point_size: Some(14.0),
+                ..Default::default()
+            },
+        )?;
+        Ok(())
+    }
+}
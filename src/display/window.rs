@@ -0,0 +1,163 @@
+use crate::config::WindowConfig;
+use crate::display::display_window::DisplayType;
+
+use glium::glutin::config::ConfigTemplateBuilder;
+use glium::glutin::context::ContextAttributesBuilder;
+use glium::glutin::display::GetGlDisplay;
+use glium::glutin::prelude::*;
+use glium::glutin::surface::{SurfaceAttributesBuilder, SwapInterval, WindowSurface};
+use glium::winit;
+use glutin_winit::DisplayBuilder;
+
+use serde::Deserialize;
+use std::error::Error;
+use std::num::NonZeroU32;
+use std::panic::AssertUnwindSafe;
+
+use winit::dpi::PhysicalSize;
+use winit::event_loop::EventLoop;
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::raw_window_handle::HasWindowHandle;
+use winit::window::{Fullscreen, Window};
+
+const DEFAULT_RESOLUTION: (u32, u32) = (1280, 720);
+
+#[derive(Deserialize, Clone)]
+pub enum FullscreenMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+//monitors can either be selected by their position in the monitor list or by name
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum MonitorSelector {
+    Index(usize),
+    Name(String),
+}
+
+pub fn build_window(
+    event_loop: &EventLoop<()>,
+    title: &str,
+    config: &WindowConfig,
+) -> Result<(Window, DisplayType), Box<dyn Error>> {
+    let (width, height) = config.resolution.unwrap_or(DEFAULT_RESOLUTION);
+    let attributes = Window::default_attributes()
+        .with_title(title)
+        .with_inner_size(PhysicalSize::new(width, height));
+
+    let display_builder = DisplayBuilder::new().with_window_attributes(Some(attributes));
+    //the config picker can't fail, so a missing config has to be caught as a panic
+    let built = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        display_builder.build(event_loop, ConfigTemplateBuilder::new(), |mut configs| {
+            configs.next().expect("no matching GL config")
+        })
+    }));
+    let (window, gl_config) = built.map_err(|_| "no matching GL config")??;
+    let window = window.ok_or("could not create window")?;
+
+    let (width, height): (u32, u32) = window.inner_size().into();
+    let surface_attributes = SurfaceAttributesBuilder::<WindowSurface>::new().build(
+        window.window_handle()?.into(),
+        NonZeroU32::new(width).ok_or("window has zero width")?,
+        NonZeroU32::new(height).ok_or("window has zero height")?,
+    );
+    let surface = unsafe {
+        gl_config
+            .display()
+            .create_window_surface(&gl_config, &surface_attributes)?
+    };
+    let context_attributes =
+        ContextAttributesBuilder::new().build(Some(window.window_handle()?.into()));
+    let context = unsafe {
+        gl_config
+            .display()
+            .create_context(&gl_config, &context_attributes)?
+    }
+    .make_current(&surface)?;
+
+    let interval = match config.vsync {
+        true => SwapInterval::Wait(NonZeroU32::MIN),
+        false => SwapInterval::DontWait,
+    };
+    if let Err(err) = surface.set_swap_interval(&context, interval) {
+        eprintln!("could not set vsync to {}: {}", config.vsync, err);
+    }
+
+    let display = glium::Display::from_context_surface(context, surface)?;
+    window.set_cursor_visible(!config.hide_cursor);
+    if let Some(monitor) = select_monitor(&window, &config.monitor) {
+        place_on_monitor(&window, monitor, config);
+    }
+    Ok((window, display))
+}
+
+fn select_monitor(window: &Window, selector: &Option<MonitorSelector>) -> Option<MonitorHandle> {
+    let found = match selector {
+        Some(MonitorSelector::Index(idx)) => window.available_monitors().nth(*idx),
+        Some(MonitorSelector::Name(name)) => window
+            .available_monitors()
+            .find(|monitor| monitor.name().as_deref() == Some(name.as_str())),
+        None => return window.current_monitor(),
+    };
+    if found.is_none() {
+        eprintln!(
+            "could not find monitor {:?}. Available monitors: {:?}",
+            selector,
+            window
+                .available_monitors()
+                .map(|monitor| monitor.name())
+                .collect::<Vec<_>>()
+        );
+    }
+    found.or_else(|| window.current_monitor())
+}
+
+fn select_video_mode(
+    monitor: &MonitorHandle,
+    resolution: Option<(u32, u32)>,
+) -> Option<VideoModeHandle> {
+    let wanted: (u32, u32) = resolution.unwrap_or(monitor.size().into());
+    monitor
+        .video_modes()
+        .filter(|mode| <(u32, u32)>::from(mode.size()) == wanted)
+        .max_by_key(|mode| (mode.refresh_rate_millihertz(), mode.bit_depth()))
+}
+
+pub fn place_on_monitor(window: &Window, monitor: MonitorHandle, config: &WindowConfig) {
+    println!("placing window on monitor {:?}", monitor.name());
+    match config.fullscreen {
+        FullscreenMode::Windowed => {
+            window.set_fullscreen(None);
+            window.set_outer_position(monitor.position());
+        }
+        FullscreenMode::Borderless => {
+            window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor))));
+        }
+        FullscreenMode::Exclusive => match select_video_mode(&monitor, config.resolution) {
+            Some(mode) => window.set_fullscreen(Some(Fullscreen::Exclusive(mode))),
+            None => {
+                eprintln!(
+                    "no video mode with resolution {:?} found. Falling back to borderless",
+                    config.resolution
+                );
+                window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor))));
+            }
+        },
+    }
+}
+
+pub fn move_to_next_monitor(window: &Window, config: &WindowConfig) {
+    let monitors: Vec<MonitorHandle> = window.available_monitors().collect();
+    if monitors.is_empty() {
+        eprintln!("no monitors available");
+        return;
+    }
+    let current = window
+        .current_monitor()
+        .and_then(|current| monitors.iter().position(|monitor| *monitor == current))
+        .unwrap_or(0);
+    let next = monitors[(current + 1) % monitors.len()].clone();
+    place_on_monitor(window, next, config);
+}
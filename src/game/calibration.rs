@@ -1,24 +1,33 @@
 use crate::PlatoConfig;
-use crate::display::display_window::DisplayType;
-use crate::display::primitves::{QUAD_INDICES, Vertex, get_quad_buffer};
+use crate::bg_subtract::{Zones, load_zones, save_zones};
+use crate::display::display_window::{DisplayType, RenderTarget};
+use crate::display::primitves::{QUAD_INDICES, Vertex, get_stream_quad_buffer};
 use crate::display::timestep::TimeStep;
-use crate::game::util::{image_to_gray_texture_r, load_shaders};
-use crate::types::GameTrait;
+use crate::game::util::{LiveViewMode, StreamTexture, WatchedProgram};
+use crate::types::{GameTrait, PointerState};
 
 use glium::draw_parameters::{DrawParameters, PolygonMode};
 use glium::implement_vertex;
 use glium::uniform;
-use glium::winit::keyboard::Key;
+use glium::winit::keyboard::{Key, NamedKey};
 use glium::{Surface, VertexBuffer};
 use opencv::prelude::*;
 
 pub struct CalibrationGame {
-    program: Option<glium::Program>,
-    live_img: Option<glium::Texture2d>,
-    line_program: Option<glium::Program>,
+    program: Option<WatchedProgram>,
+    live_img: StreamTexture,
+    live_view_mode: LiveViewMode,
+    line_program: Option<WatchedProgram>,
     vertex_buffer: Option<glium::VertexBuffer<Vertex>>,
     line_buffer: Option<glium::VertexBuffer<LineVertex>>,
     index_buffer: Option<glium::IndexBuffer<u16>>,
+    zones_path: String,
+    zones: Zones,
+    edit_zones_key: String,
+    roi_key: String,
+    //polygon that is being drawn, in relative image coordinates like the zones
+    editing: Option<Vec<(f64, f64)>>,
+    pointer: PointerState,
 }
 #[derive(Copy, Clone)]
 pub struct LineVertex {
@@ -31,13 +40,149 @@ impl CalibrationGame {
     pub fn new() -> CalibrationGame {
         CalibrationGame {
             program: None,
-            live_img: None,
+            live_img: StreamTexture::new(),
+            live_view_mode: LiveViewMode::Gray,
             line_program: None,
             vertex_buffer: None,
             line_buffer: None,
             index_buffer: None,
+            zones_path: String::new(),
+            zones: Zones::default(),
+            edit_zones_key: String::new(),
+            roi_key: String::new(),
+            editing: None,
+            pointer: PointerState::default(),
         }
     }
+
+    //leaving the edit mode saves the zones, the pipeline picks them up from the file
+    fn toggle_editing(&mut self) {
+        self.editing = match self.editing.take() {
+            Some(_) => {
+                match save_zones(&self.zones_path, &self.zones) {
+                    Ok(()) => println!("saved zones to {}", self.zones_path),
+                    Err(err) => eprintln!("could not save zones to {}: {}", self.zones_path, err),
+                }
+                None
+            }
+            None => {
+                println!(
+                    "editing zones. Click to add points, enter finishes an exclusion zone, {} the roi",
+                    self.roi_key
+                );
+                Some(Vec::new())
+            }
+        };
+    }
+
+    fn finish_polygon(&mut self, roi: bool) {
+        let Some(polygon) = self.editing.as_mut() else {
+            return;
+        };
+        if polygon.len() < 3 {
+            println!("a zone needs at least three points");
+            return;
+        }
+        let polygon = std::mem::take(polygon);
+        match roi {
+            true => self.zones.roi = Some(polygon),
+            false => self.zones.exclusions.push(polygon),
+        }
+    }
+
+    //removes the last point, or the last zone if no polygon is being drawn
+    fn undo(&mut self) {
+        let Some(polygon) = self.editing.as_mut() else {
+            return;
+        };
+        if polygon.pop().is_none() && self.zones.exclusions.pop().is_none() {
+            self.zones.roi = None;
+        }
+    }
+
+    fn clear_zones(&mut self) {
+        self.zones = Zones::default();
+        if let Some(polygon) = self.editing.as_mut() {
+            polygon.clear();
+        }
+    }
+
+    fn draw_lines(
+        &self,
+        frame: &mut RenderTarget,
+        display: &DisplayType,
+        vertices: &[LineVertex],
+        line_color: [f32; 3],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if vertices.is_empty() {
+            return Ok(());
+        }
+        let params = DrawParameters {
+            line_width: Some(3_f32),
+            ..Default::default()
+        };
+        frame.draw(
+            &VertexBuffer::new(display, vertices)?,
+            glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+            self.line_program.as_ref().ok_or("no program")?,
+            &uniform! {line_color: line_color},
+            &params,
+        )?;
+        Ok(())
+    }
+
+    //exclusions are red, the roi green and the polygon that is being drawn yellow
+    fn draw_zones(
+        &self,
+        frame: &mut RenderTarget,
+        display: &DisplayType,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let exclusions: Vec<LineVertex> = self
+            .zones
+            .exclusions
+            .iter()
+            .flat_map(|polygon| polygon_lines(polygon, true))
+            .collect();
+        self.draw_lines(frame, display, &exclusions, [1.0, 0.2, 0.2])?;
+        if let Some(roi) = self.zones.roi.as_ref() {
+            self.draw_lines(frame, display, &polygon_lines(roi, true), [0.2, 1.0, 0.2])?;
+        }
+        if let Some(polygon) = self.editing.as_ref() {
+            let mut points = polygon.clone();
+            points.push(gl_to_image(self.pointer.position));
+            self.draw_lines(
+                frame,
+                display,
+                &polygon_lines(&points, false),
+                [1.0, 1.0, 0.2],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//zones are stored like the camera image, which is mirrored on both axes compared to
+//opengl coordinates
+fn gl_to_image((x, y): (f32, f32)) -> (f64, f64) {
+    (((1.0 - x) / 2.0) as f64, ((1.0 - y) / 2.0) as f64)
+}
+
+fn image_to_gl((x, y): (f64, f64)) -> LineVertex {
+    LineVertex {
+        position: [(1.0 - 2.0 * x) as f32, (1.0 - 2.0 * y) as f32],
+    }
+}
+
+//pairs of vertices for a LinesList
+fn polygon_lines(points: &[(f64, f64)], closed: bool) -> Vec<LineVertex> {
+    let segments = match closed {
+        true => points.len(),
+        false => points.len().saturating_sub(1),
+    };
+    (0..segments)
+        .flat_map(|i| [points[i], points[(i + 1) % points.len()]])
+        .map(image_to_gl)
+        .collect()
 }
 
 fn generate_line_buffer(display: &DisplayType) -> VertexBuffer<LineVertex> {
@@ -122,17 +267,29 @@ impl GameTrait for CalibrationGame {
     fn init(
         &mut self,
         display: &DisplayType,
-        _config: PlatoConfig,
+        config: PlatoConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let verticies = get_quad_buffer((-1_f32, 1_f32), (-1_f32, 1_f32));
+        self.live_view_mode = config.calibration_config.live_view_mode;
+        self.edit_zones_key = config.calibration_config.edit_zones_key;
+        self.roi_key = config.calibration_config.roi_key;
+        self.zones_path = config.background_subtractor_config.zones_path;
+        //this is where broken zones get fixed, so they must not stop the game
+        self.zones = load_zones(&self.zones_path).unwrap_or_else(|error| {
+            eprintln!(
+                "could not load zones from {}, starting without zones: {}",
+                self.zones_path, error
+            );
+            Zones::default()
+        });
+        let verticies = get_stream_quad_buffer((-1_f32, 1_f32), (-1_f32, 1_f32));
         let vertex_buffer = glium::VertexBuffer::new(display, &verticies)?;
         let index_buffer = glium::IndexBuffer::new(
             display,
             glium::index::PrimitiveType::TrianglesList,
             &QUAD_INDICES,
         )?;
-        let program = load_shaders("src/shaders/calibration.toml", display)?;
-        let line_program = load_shaders("src/shaders/calibration_lines.toml", display)?;
+        let program = WatchedProgram::load("src/shaders/calibration.toml", display)?;
+        let line_program = WatchedProgram::load("src/shaders/calibration_lines.toml", display)?;
 
         self.vertex_buffer = Some(vertex_buffer);
         self.index_buffer = Some(index_buffer);
@@ -148,21 +305,21 @@ impl GameTrait for CalibrationGame {
         _mask: &Mat,
         display: &DisplayType,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.live_img = Some(image_to_gray_texture_r(display, image)?);
-        Ok(())
+        self.live_img.upload(display, image, self.live_view_mode)
     }
 
     fn draw(
         &mut self,
-        frame: &mut glium::Frame,
-        _display: &DisplayType,
+        frame: &mut RenderTarget,
+        display: &DisplayType,
         _timestep: &TimeStep,
     ) -> Result<(), Box<dyn std::error::Error>> {
         frame.draw(
             self.vertex_buffer.as_ref().ok_or("no vertext buffer")?,
             self.index_buffer.as_ref().ok_or("no index buffer")?,
             self.program.as_ref().ok_or("no program")?,
-            &uniform! {tex : self.live_img.as_ref().ok_or("no image")?},
+            &uniform! {tex : self.live_img.texture().ok_or("no image")?,
+            color_tex: self.live_img.is_color()},
             &glium::DrawParameters::default(),
         )?;
 
@@ -175,12 +332,42 @@ impl GameTrait for CalibrationGame {
             self.line_buffer.as_ref().ok_or("no line vertext buffer")?,
             glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
             self.line_program.as_ref().ok_or("no program")?,
-            &glium::uniforms::EmptyUniforms {},
+            &uniform! {line_color: [1_f32, 1_f32, 1_f32]},
             &params,
         )?;
-        Ok(())
+        self.draw_zones(frame, display)
     }
 
-    fn key_event(&mut self, _event: &Key) {}
+    fn key_event(&mut self, event: &Key) {
+        match event.as_ref() {
+            Key::Character(val) if val.to_lowercase() == self.edit_zones_key => {
+                self.toggle_editing()
+            }
+            _ if self.editing.is_none() => (),
+            Key::Character(val) if val.to_lowercase() == self.roi_key => self.finish_polygon(true),
+            Key::Named(NamedKey::Enter) => self.finish_polygon(false),
+            Key::Named(NamedKey::Backspace) => self.undo(),
+            Key::Named(NamedKey::Delete) => self.clear_zones(),
+            _ => (),
+        }
+    }
+
+    //clicks add points to the polygon that is being drawn
+    fn pointer_event(&mut self, pointer: &PointerState) {
+        let clicked = pointer.pressed && !self.pointer.pressed;
+        self.pointer = *pointer;
+        if let (true, Some(polygon)) = (clicked, self.editing.as_mut()) {
+            polygon.push(gl_to_image(pointer.position));
+        }
+    }
     fn reset(&mut self) {}
+
+    fn reload_shaders(&mut self, display: &DisplayType) {
+        for program in [self.program.as_mut(), self.line_program.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            program.reload_if_changed(display);
+        }
+    }
 }
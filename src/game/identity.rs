@@ -1,18 +1,19 @@
 use crate::PlatoConfig;
-use crate::display::display_window::DisplayType;
-use crate::display::primitves::{QUAD_INDICES, Vertex, get_quad_buffer};
+use crate::display::display_window::{DisplayType, RenderTarget};
+use crate::display::primitves::{QUAD_INDICES, Vertex, get_stream_quad_buffer};
 use crate::display::timestep::TimeStep;
-use crate::game::util::{load_shaders, mat_1c_to_texture_r};
-use crate::types::GameTrait;
+use crate::game::util::{StreamTexture, WatchedProgram};
+use crate::types::{GameTrait, MaskChannels};
 
 use glium::Surface;
 use glium::uniform;
 use glium::winit::keyboard::Key;
+use opencv::core::CV_8U;
 use opencv::prelude::*;
 
 pub struct IdentityGame {
-    current_mask: Option<glium::Texture2d>,
-    program: Option<glium::Program>,
+    current_mask: StreamTexture,
+    program: Option<WatchedProgram>,
     vertex_buffer: Option<glium::VertexBuffer<Vertex>>,
     index_buffer: Option<glium::IndexBuffer<u16>>,
 }
@@ -20,7 +21,7 @@ pub struct IdentityGame {
 impl IdentityGame {
     pub fn new() -> IdentityGame {
         IdentityGame {
-            current_mask: None,
+            current_mask: StreamTexture::new(),
             program: None,
             vertex_buffer: None,
             index_buffer: None,
@@ -34,14 +35,14 @@ impl GameTrait for IdentityGame {
         display: &DisplayType,
         _config: PlatoConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let verticies = get_quad_buffer((-1_f32, 1_f32), (-1_f32, 1_f32));
+        let verticies = get_stream_quad_buffer((-1_f32, 1_f32), (-1_f32, 1_f32));
         let vertex_buffer = glium::VertexBuffer::new(display, &verticies)?;
         let index_buffer = glium::IndexBuffer::new(
             display,
             glium::index::PrimitiveType::TrianglesList,
             &QUAD_INDICES,
         )?;
-        let program = load_shaders("src/shaders/minimap.toml", display)?;
+        let program = WatchedProgram::load("src/shaders/minimap.toml", display)?;
 
         self.vertex_buffer = Some(vertex_buffer);
         self.index_buffer = Some(index_buffer);
@@ -55,13 +56,26 @@ impl GameTrait for IdentityGame {
         mask: &Mat,
         display: &DisplayType,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.current_mask = Some(mat_1c_to_texture_r(display, mask)?);
-        Ok(())
+        self.current_mask.upload_r(display, mask)
+    }
+
+    //subtractors with a confidence map get feathered edges instead of the hard mask
+    fn update_channels(
+        &mut self,
+        channels: &MaskChannels,
+        display: &DisplayType,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(confidence) = channels.confidence.as_ref() else {
+            return Ok(());
+        };
+        let mut feathered = Mat::default();
+        confidence.convert_to(&mut feathered, CV_8U, 255.0, 0.0)?;
+        self.current_mask.upload_r(display, &feathered)
     }
 
     fn draw(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         _display: &DisplayType,
         _timestep: &TimeStep,
     ) -> Result<(), Box<dyn std::error::Error>> {
@@ -69,7 +83,8 @@ impl GameTrait for IdentityGame {
             self.vertex_buffer.as_ref().ok_or("no vertext buffer")?,
             self.index_buffer.as_ref().ok_or("no index buffer")?,
             self.program.as_ref().ok_or("no program")?,
-            &uniform! { tex: self.current_mask.as_ref().ok_or("no texture")?},
+            &uniform! { tex: self.current_mask.texture().ok_or("no texture")?,
+            color_tex: self.current_mask.is_color()},
             &glium::DrawParameters::default(),
         )?;
         Ok(())
@@ -77,4 +92,10 @@ impl GameTrait for IdentityGame {
 
     fn key_event(&mut self, _event: &Key) {}
     fn reset(&mut self) {}
+
+    fn reload_shaders(&mut self, display: &DisplayType) {
+        if let Some(program) = self.program.as_mut() {
+            program.reload_if_changed(display);
+        }
+    }
 }
@@ -7,4 +7,3 @@ pub mod util;
 pub use calibration::CalibrationGame;
 pub use identity::IdentityGame;
 pub use skull_game::SkullGame;
-pub use util::load_shaders;
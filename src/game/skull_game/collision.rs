@@ -0,0 +1,116 @@
+use opencv::core::CV_32F;
+use opencv::imgproc::{DIST_L2, DIST_MASK_3, distance_transform};
+use opencv::prelude::*;
+use serde::Deserialize;
+
+//what happens when a particle runs into a silhouette.
+//Bounce reflects the velocity and scales it by the given restitution
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Collision {
+    Bounce(f32),
+    Slide,
+    Stick,
+}
+
+//distance transform of the mask. Inside a silhouette it holds the distance to its border,
+//outside it is zero. The gradient points into the silhouette, so its negative is the
+//surface normal particles are pushed out along
+pub struct CollisionField {
+    distance: Vec<f32>,
+    cols: i32,
+    rows: i32,
+}
+
+impl CollisionField {
+    pub fn from_mask(mask: &Mat) -> Result<CollisionField, Box<dyn std::error::Error>> {
+        let mut distance = Mat::default();
+        distance_transform(mask, &mut distance, DIST_L2, DIST_MASK_3, CV_32F)?;
+        Ok(CollisionField {
+            distance: distance.data_typed::<f32>()?.to_vec(),
+            cols: distance.cols(),
+            rows: distance.rows(),
+        })
+    }
+
+    //the mask is mirrored on both axes compared to opengl coordinates
+    fn to_pixel(&self, pos: (f32, f32)) -> Option<(i32, i32)> {
+        let col = ((1_f32 - pos.0) / 2_f32 * self.cols as f32) as i32;
+        let row = ((1_f32 - pos.1) / 2_f32 * self.rows as f32) as i32;
+        match (0..self.cols).contains(&col) && (0..self.rows).contains(&row) {
+            true => Some((col, row)),
+            false => None,
+        }
+    }
+
+    fn distance_at(&self, col: i32, row: i32) -> f32 {
+        let col = col.clamp(0, self.cols - 1);
+        let row = row.clamp(0, self.rows - 1);
+        self.distance[(row * self.cols + col) as usize]
+    }
+
+    pub fn is_inside(&self, pos: (f32, f32)) -> bool {
+        self.to_pixel(pos)
+            .is_some_and(|(col, row)| self.distance_at(col, row) > 0_f32)
+    }
+
+    //outward normal in opengl coordinates. Moving along x or y in opengl coordinates
+    //decreases col or row, which cancels the minus of the negative gradient
+    pub fn normal_at(&self, pos: (f32, f32)) -> Option<(f32, f32)> {
+        let (col, row) = self.to_pixel(pos)?;
+        let d_col = self.distance_at(col + 1, row) - self.distance_at(col - 1, row);
+        let d_row = self.distance_at(col, row + 1) - self.distance_at(col, row - 1);
+        let normal = (d_col * self.cols as f32, d_row * self.rows as f32);
+        let magnitude = (normal.0 * normal.0 + normal.1 * normal.1).sqrt();
+        match magnitude > 0_f32 {
+            true => Some((normal.0 / magnitude, normal.1 / magnitude)),
+            false => None,
+        }
+    }
+}
+
+//resolves a particle that moved from previous to center. Particles only collide when they
+//enter a silhouette, so particles spawned inside one can leave it
+pub fn collide(
+    field: &CollisionField,
+    collision: Collision,
+    previous: (f32, f32),
+    center: &mut (f32, f32),
+    velocity: &mut (f32, f32),
+    stuck: &mut bool,
+    dt: f32,
+) {
+    if !field.is_inside(*center) || field.is_inside(previous) {
+        return;
+    }
+    //without a usable gradient the particle is sent back where it came from
+    let speed = (velocity.0 * velocity.0 + velocity.1 * velocity.1).sqrt();
+    let normal = field.normal_at(*center).unwrap_or(match speed > 0_f32 {
+        true => (-velocity.0 / speed, -velocity.1 / speed),
+        false => (0_f32, 0_f32),
+    });
+    let normal_speed = velocity.0 * normal.0 + velocity.1 * normal.1;
+
+    *center = previous;
+    match collision {
+        Collision::Stick => {
+            *velocity = (0_f32, 0_f32);
+            *stuck = true;
+        }
+        Collision::Slide => {
+            if normal_speed < 0_f32 {
+                velocity.0 -= normal_speed * normal.0;
+                velocity.1 -= normal_speed * normal.1;
+            }
+            center.0 += velocity.0 * dt;
+            center.1 += velocity.1 * dt;
+        }
+        Collision::Bounce(restitution) => {
+            if normal_speed < 0_f32 {
+                velocity.0 -= 2_f32 * normal_speed * normal.0;
+                velocity.1 -= 2_f32 * normal_speed * normal.1;
+            }
+            velocity.0 *= restitution;
+            velocity.1 *= restitution;
+        }
+    }
+}
@@ -1,20 +1,54 @@
+use crate::game::skull_game::collision::Collision;
+use crate::game::skull_game::emitter::{BehaviourType, ParticleEvent, TargetType};
+use crate::game::util::LiveViewMode;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 type ListToLoad = Vec<(String, String)>;
 
+//number particles are spawned per emission in an area around the emitting object.
+//area is relative to the size of that object. Without a direction particles move away
+//from the emission point, spread is the random variation of the direction in radians.
+//color_over_life and size_over_life are evenly spaced keyframes over the lifetime in seconds.
+//With a collision the particles react to the silhouettes in the mask
 #[derive(Deserialize, Clone)]
-pub struct ParticleSetting {
-    pub scale: f32,
-    pub color: (f32, f32, f32),
-    pub opacity: f32,
-    pub initial_velocity: f32,
+pub struct EmitterSettings {
+    pub behaviour: BehaviourType,
+    pub target: TargetType,
+    pub collision: Option<Collision>,
+    pub gravity: f32,
+    pub target_size: (f32, f32),
     pub number: usize,
+    pub lifetime: f32,
+    pub area: f32,
+    pub direction: Option<(f32, f32)>,
+    pub spread: f32,
+    pub speed: (f32, f32),
+    pub scale: (f32, f32),
+    pub color_over_life: Vec<[f32; 4]>,
+    pub size_over_life: Vec<f32>,
+}
+
+//maps game events to the emitters they trigger. Events without an emitter don't emit
+#[derive(Deserialize, Clone)]
+pub struct EventEmitters {
+    pub mask_motion_points: usize,
+    pub emitters: HashMap<ParticleEvent, String>,
 }
+//budget is the maximal number of particles alive at once. Large particle counts are
+//updated in parallel, if parallel_update is set
+#[derive(Deserialize, Clone)]
+pub struct ParticleSystemSettings {
+    pub budget: usize,
+    pub parallel_update: bool,
+    pub parallel_threshold: usize,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct ParticleSettings {
-    pub escaped: ParticleSetting,
-    pub killed: ParticleSetting,
-    pub visualization: ParticleSetting,
+    pub system: ParticleSystemSettings,
+    pub emitters: HashMap<String, EmitterSettings>,
+    pub events: EventEmitters,
 }
 
 #[derive(Deserialize, Clone)]
@@ -80,6 +114,7 @@ pub struct GameSettings {
     pub key_settings: KeySettings,
     pub difficultiy_settings: DifficultySettings,
     pub texture_settings: TextureSettings,
+    pub live_view_mode: LiveViewMode,
     pub number_of_rounds: u32,
     pub number_of_kill_sounds: u32,
     pub number_of_escape_sounds: u32,
@@ -106,6 +141,22 @@ pub fn valdiate_config(settings: &GameSettings) -> Result<(), Box<dyn std::error
         .into());
     }
 
+    for (event, emitter) in settings.particle_settings.events.emitters.iter() {
+        if !settings.particle_settings.emitters.contains_key(emitter) {
+            return Err(
+                format!("particle emitter {} of {:?} is not defined", emitter, event).into(),
+            );
+        }
+    }
+
+    //a zero direction can't be normalized, leaving it out lets particles move away from
+    //the emission point
+    for (name, emitter) in settings.particle_settings.emitters.iter() {
+        if emitter.direction == Some((0_f32, 0_f32)) {
+            return Err(format!("direction of particle emitter {} is zero", name).into());
+        }
+    }
+
     //todo check if all sounds are there
     Ok(())
 }
@@ -0,0 +1,180 @@
+use std::f32::consts::PI;
+
+use crate::game::skull_game::config::EmitterSettings;
+use crate::game::skull_game::particle::{Behaviour, Particle, Target};
+
+use rand::{Rng, rng};
+use serde::Deserialize;
+
+//selects how particles of an emitter move, see Behaviour
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum BehaviourType {
+    Gravity,
+    Linear,
+    Repulsed,
+}
+
+//what particles are attracted to or repulsed from
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum TargetType {
+    //the moon at the time of emission. The target size is relative to the moon scale
+    Moon,
+    //the point the particles were emitted from
+    Origin,
+    //the top of the screen above each particle
+    Top,
+    //a fixed point in screen coordinates
+    Point((f32, f32)),
+}
+
+//game events that can trigger an emitter. Round and game events emit at the moon
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParticleEvent {
+    SkullKilled,
+    SkullEscaped,
+    MaskMotion,
+    RoundStarted,
+    RoundWon,
+    GameWon,
+}
+
+//positions of game objects that can be used as targets. They change during the game,
+//so they are passed on every emission
+#[derive(Debug, Clone, Copy)]
+pub struct TargetPositions {
+    pub moon_center: (f32, f32),
+    pub moon_scale: (f32, f32),
+}
+
+fn get_random_point_in_area(point: (f32, f32), area: f32) -> (f32, f32) {
+    let mut randomizer = rng();
+    let r = randomizer.random_range(0_f32..=area / 2_f32);
+    let phi = randomizer.random_range(0_f32..2_f32 * PI);
+
+    let x = phi.cos() * r + point.0;
+    let y = phi.sin() * r + point.1;
+    (x, y)
+}
+
+fn get_random_direction(
+    settings: &EmitterSettings,
+    origin: (f32, f32),
+    position: (f32, f32),
+) -> (f32, f32) {
+    let mut randomizer = rng();
+    //without a direction particles move away from the origin
+    let (dx, dy) = match settings.direction {
+        Some(direction) => direction,
+        None if origin != position => (position.0 - origin.0, position.1 - origin.1),
+        None => {
+            let phi = randomizer.random_range(0_f32..2_f32 * PI);
+            (phi.cos(), phi.sin())
+        }
+    };
+    let angle = match settings.spread > 0_f32 {
+        true => randomizer.random_range(-settings.spread / 2_f32..=settings.spread / 2_f32),
+        false => 0_f32,
+    };
+    let magnitude = (dx * dx + dy * dy).sqrt();
+    let (dx, dy) = (dx / magnitude, dy / magnitude);
+    (
+        angle.cos() * dx - angle.sin() * dy,
+        angle.sin() * dx + angle.cos() * dy,
+    )
+}
+
+fn get_target(
+    settings: &EmitterSettings,
+    origin: (f32, f32),
+    position: (f32, f32),
+    targets: &TargetPositions,
+) -> Target {
+    let (center, size) = match settings.target {
+        TargetType::Moon => (
+            targets.moon_center,
+            (
+                settings.target_size.0 * targets.moon_scale.0,
+                settings.target_size.1 * targets.moon_scale.1,
+            ),
+        ),
+        TargetType::Origin => (origin, settings.target_size),
+        TargetType::Top => ((position.0, 1_f32), settings.target_size),
+        TargetType::Point(point) => (point, settings.target_size),
+    };
+    Target {
+        center,
+        gravity: settings.gravity,
+        size,
+    }
+}
+
+fn random_in_range(range: (f32, f32)) -> f32 {
+    match range.0 < range.1 {
+        true => rng().random_range(range.0..range.1),
+        false => range.0,
+    }
+}
+
+//creates the particles of a single emission. scale is the size of the emitting object,
+//the spawn area of the emitter is relative to it
+pub fn emit_particles(
+    settings: &EmitterSettings,
+    emitter_id: usize,
+    origin: (f32, f32),
+    scale: f32,
+    targets: &TargetPositions,
+) -> Vec<Particle> {
+    (0..settings.number)
+        .map(|_| {
+            let position = get_random_point_in_area(origin, settings.area * scale);
+            let direction = get_random_direction(settings, origin, position);
+            let speed = random_in_range(settings.speed);
+            let target = get_target(settings, origin, position, targets);
+            let behaviour = match settings.behaviour {
+                BehaviourType::Gravity => Behaviour::Gravity(target),
+                BehaviourType::Linear => Behaviour::Linear(target),
+                BehaviourType::Repulsed => Behaviour::Repulsed(target),
+            };
+            Particle::new(
+                position,
+                random_in_range(settings.scale),
+                (direction.0 * speed, direction.1 * speed),
+                settings.lifetime,
+                behaviour,
+                settings.collision,
+                emitter_id,
+            )
+        })
+        .collect()
+}
+
+//position of t in a list of evenly spaced keyframes
+fn keyframe_position(number_of_keys: usize, t: f32) -> (usize, usize, f32) {
+    let last = number_of_keys.saturating_sub(1);
+    let pos = t.clamp(0_f32, 1_f32) * last as f32;
+    let idx = (pos.floor() as usize).min(last);
+    (idx, (idx + 1).min(last), pos - idx as f32)
+}
+
+//t is the fraction of the lifetime that has passed
+pub fn sample_color_over_life(settings: &EmitterSettings, t: f32) -> [f32; 4] {
+    let keys = &settings.color_over_life;
+    if keys.is_empty() {
+        return [1_f32; 4];
+    }
+    let (start, end, blend) = keyframe_position(keys.len(), t);
+    let mut color = [0_f32; 4];
+    for (i, channel) in color.iter_mut().enumerate() {
+        *channel = keys[start][i] * (1_f32 - blend) + keys[end][i] * blend;
+    }
+    color
+}
+
+pub fn sample_size_over_life(settings: &EmitterSettings, t: f32) -> f32 {
+    let keys = &settings.size_over_life;
+    if keys.is_empty() {
+        return 1_f32;
+    }
+    let (start, end, blend) = keyframe_position(keys.len(), t);
+    keys[start] * (1_f32 - blend) + keys[end] * blend
+}
@@ -1,14 +1,13 @@
 use crate::display::display_window::DisplayType;
 use crate::display::primitves::{QUAD_INDICES, Vertex, get_quad_buffer};
-use crate::game::util::image_to_gray_texture_r;
+use crate::game::util::{LiveViewMode, StreamTexture};
 use glium::{IndexBuffer, VertexBuffer};
 use opencv::prelude::*;
-use std::sync::{Arc, Mutex};
 
 pub struct LiveViewData {
     pub live_view_vb: VertexBuffer<Vertex>,
     pub live_view_ib: IndexBuffer<u16>,
-    pub live_view_texture: Arc<Mutex<Option<glium::Texture2d>>>,
+    pub live_view_texture: StreamTexture,
 }
 
 impl LiveViewData {
@@ -25,7 +24,7 @@ impl LiveViewData {
         Ok(LiveViewData {
             live_view_vb,
             live_view_ib,
-            live_view_texture: Arc::new(Mutex::new(None)),
+            live_view_texture: StreamTexture::new(),
         })
     }
 
@@ -33,8 +32,8 @@ impl LiveViewData {
         &mut self,
         display: &DisplayType,
         live_img: &Mat,
+        mode: LiveViewMode,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        *self.live_view_texture.lock().unwrap() = Some(image_to_gray_texture_r(display, live_img)?);
-        Ok(())
+        self.live_view_texture.upload(display, live_img, mode)
     }
 }
@@ -1,4 +1,6 @@
+mod collision;
 mod config;
+mod emitter;
 mod live_view;
 mod moon;
 mod particle;
@@ -1,11 +1,8 @@
-use ::glium::{IndexBuffer, VertexBuffer};
-use glium::implement_vertex;
-
+use crate::display::display_window::DisplayType;
+use crate::display::sprite::{SpriteInstance, SpriteRenderer};
 use crate::game::skull_game::config::MoonSettings;
+use crate::game::skull_game::emitter::TargetPositions;
 use crate::game::util::Interpolator;
-use crate::{
-    display::display_window::DisplayType, game::skull_game::util::generate_index_for_quad,
-};
 
 #[derive(Copy, Clone)]
 pub enum MoonState {
@@ -26,62 +23,15 @@ pub struct Moon {
     pub state: MoonState,
 }
 
-#[derive(Copy, Clone)]
-pub struct MoonVertex {
-    pub position: [f32; 2],
-    pub uv: [f32; 2],
-    pub blend_value: f32,
-    pub texture_id: f32,
-}
-implement_vertex!(MoonVertex, position, uv, texture_id, blend_value,);
-
-pub fn create_moon_vertex_buffer(
-    moon: &Moon,
-    scale: f32,
-    display: &DisplayType,
-) -> Result<(glium::VertexBuffer<MoonVertex>, glium::IndexBuffer<u32>), Box<dyn std::error::Error>>
-{
-    let mut moon_vertex_buffer: glium::VertexBuffer<MoonVertex> =
-        glium::VertexBuffer::empty_dynamic(display, 4)?;
-    let mut moon_ib: Vec<u32> = Vec::with_capacity(4);
-    let blend_value = moon.get_life_fraction();
-    let position = moon.current_position;
-    let final_scale = (moon.scale.0 * scale, moon.scale.1 * scale);
-    {
-        let moon_vb = &mut moon_vertex_buffer.map();
-
-        moon_vb[0].position[0] = position.0 - final_scale.0;
-        moon_vb[0].position[1] = position.1 + final_scale.1;
-        moon_vb[0].uv[0] = 0_f32;
-        moon_vb[0].uv[1] = 0_f32;
-
-        moon_vb[1].position[0] = position.0 + final_scale.0;
-        moon_vb[1].position[1] = position.1 + final_scale.1;
-        moon_vb[1].uv[0] = 1_f32;
-        moon_vb[1].uv[1] = 0_f32;
-
-        moon_vb[2].position[0] = position.0 - final_scale.0;
-        moon_vb[2].position[1] = position.1 - final_scale.1;
-        moon_vb[2].uv[0] = 0_f32;
-        moon_vb[2].uv[1] = 1_f32;
-
-        moon_vb[3].position[0] = position.0 + final_scale.0;
-        moon_vb[3].position[1] = position.1 - final_scale.1;
-        moon_vb[3].uv[0] = 1_f32;
-        moon_vb[3].uv[1] = 1_f32;
-
-        for i in 0..4 {
-            moon_vb[i].texture_id = 0_f32;
-            moon_vb[i].blend_value = blend_value;
-        }
+//the moon scale is half the size of the moon
+pub fn create_moon_instance(moon: &Moon, scale: f32) -> SpriteInstance {
+    SpriteInstance {
+        center: [moon.current_position.0, moon.current_position.1],
+        scale: [2_f32 * moon.scale.0 * scale, 2_f32 * moon.scale.1 * scale],
+        rotation: 0_f32,
+        color: [1_f32, 1_f32, 1_f32, moon.get_life_fraction()],
+        texture_layer: 0_f32,
     }
-    generate_index_for_quad(0, &mut moon_ib);
-    let moon_idxb: glium::IndexBuffer<u32> = glium::IndexBuffer::new(
-        display,
-        glium::index::PrimitiveType::TrianglesList,
-        &moon_ib,
-    )?;
-    Ok((moon_vertex_buffer, moon_idxb))
 }
 
 impl Moon {
@@ -115,6 +65,13 @@ impl Moon {
         self.life.current_value / self.max_life as f32
     }
 
+    pub fn get_target_positions(&self) -> TargetPositions {
+        TargetPositions {
+            moon_center: self.current_position,
+            moon_scale: self.scale,
+        }
+    }
+
     pub fn update_position(&mut self) {
         self.current_position = self.get_position();
     }
@@ -133,43 +90,49 @@ impl Moon {
         println!("got healed {:?}", self.life.current_value);
     }
 }
-fn get_moon_data(
-    moon: Moon,
+fn update_moon_sprites(
+    moon_data: &mut MoonData,
     display: &DisplayType,
-) -> Result<MoonData, Box<dyn std::error::Error>> {
-    let (moon_vb, moon_idxb) = create_moon_vertex_buffer(&moon, 1_f32, display)?;
-    let (corona_vb, corona_idxb) = create_moon_vertex_buffer(&moon, 1.25_f32, display)?;
-    Ok(MoonData {
-        moon_vb,
-        moon_idxb,
-        corona_vb,
-        corona_idxb,
-        moon,
-    })
+) -> Result<(), Box<dyn std::error::Error>> {
+    moon_data.moon_sprite.clear();
+    moon_data
+        .moon_sprite
+        .push(create_moon_instance(&moon_data.moon, 1_f32));
+    moon_data.moon_sprite.upload(display)?;
+
+    moon_data.corona_sprite.clear();
+    moon_data
+        .corona_sprite
+        .push(create_moon_instance(&moon_data.moon, 1.25_f32));
+    moon_data.corona_sprite.upload(display)
 }
+
 pub fn create_moon_data(
     display: &DisplayType,
     settings: &MoonSettings,
 ) -> Result<MoonData, Box<dyn std::error::Error>> {
-    let moon = Moon::new(settings.clone());
-    get_moon_data(moon, display)
+    let mut moon_data = MoonData {
+        moon_sprite: SpriteRenderer::new(display, 1)?,
+        corona_sprite: SpriteRenderer::new(display, 1)?,
+        moon: Moon::new(settings.clone()),
+    };
+    update_moon_sprites(&mut moon_data, display)?;
+    Ok(moon_data)
 }
 
 pub fn update_moon_data(
     moon_data: &mut MoonData,
     display: &DisplayType,
     time_step: f32,
-) -> Result<MoonData, Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>> {
     moon_data.moon.update_position();
     moon_data.moon.life.update(time_step);
 
-    get_moon_data(moon_data.moon.clone(), display)
+    update_moon_sprites(moon_data, display)
 }
 
 pub struct MoonData {
-    pub moon_vb: VertexBuffer<MoonVertex>,
-    pub moon_idxb: IndexBuffer<u32>,
-    pub corona_vb: VertexBuffer<MoonVertex>,
-    pub corona_idxb: IndexBuffer<u32>,
+    pub moon_sprite: SpriteRenderer,
+    pub corona_sprite: SpriteRenderer,
     pub moon: Moon,
 }
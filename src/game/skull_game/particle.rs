@@ -1,13 +1,16 @@
-use std::f32::consts::PI;
+use std::collections::HashMap;
 
 use crate::display::display_window::DisplayType;
-use crate::game::skull_game::config::ParticleSetting;
-use ::glium::{IndexBuffer, VertexBuffer};
-use glium::implement_vertex;
-use rand::{Rng, rng};
-
-use crate::display::timestep::TimeStep;
-use crate::game::skull_game::util::generate_index_for_quad;
+use crate::display::sprite::{SpriteInstance, SpriteRenderer};
+use crate::game::skull_game::collision::{Collision, CollisionField, collide};
+use crate::game::skull_game::config::{
+    EmitterSettings, EventEmitters, ParticleSettings, ParticleSystemSettings,
+};
+use crate::game::skull_game::emitter::{
+    ParticleEvent, TargetPositions, emit_particles, sample_color_over_life, sample_size_over_life,
+};
+use opencv::prelude::*;
+use rayon::prelude::*;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Target {
@@ -16,318 +19,401 @@ pub struct Target {
     pub size: (f32, f32),
 }
 
+//how a particle moves and when it dies
 #[derive(Debug, Clone, Copy)]
-pub enum ParticleState {
-    Alive,
-    ToRemove,
+pub enum Behaviour {
+    //accelerates towards the target and dies inside the target ellipse
+    Gravity(Target),
+    //moves with constant velocity and dies after passing the target
+    Linear(Target),
+    //accelerates away from the target and dies when leaving the screen
+    Repulsed(Target),
 }
 
+//description of a single particle, used for spawning. The simulation itself
+//stores particles in a ParticleSystem
 #[derive(Debug, Clone, Copy)]
 pub struct Particle {
-    color: (f32, f32, f32),
     center: (f32, f32),
-    update_function: fn(&mut Particle),
-    target: Target,
     scale: f32,
-    opacity: f32,
     velocity: (f32, f32),
-    timer: TimeStep,
-    pub state: ParticleState,
-}
-#[derive(Copy, Clone)]
-pub struct ParticleVertex {
-    pub position: [f32; 2],
-    pub uv: [f32; 2],
-    pub color: [f32; 3],
-    pub blend_value: f32,
+    lifetime: f32,
+    behaviour: Behaviour,
+    collision: Option<Collision>,
+    emitter_id: usize,
 }
 
-implement_vertex!(ParticleVertex, position, uv, color, blend_value,);
-
-pub fn create_particle_vertex_buffer(
-    skull_vb: &mut glium::VertexBuffer<ParticleVertex>,
-    skulls: &Vec<Particle>,
-    index_buffer_data: &mut Vec<u32>,
-) {
-    for (i, (particle, vb_entry)) in skulls.iter().zip(skull_vb.map().chunks_mut(4)).enumerate() {
-        let radius = particle.scale / 2_f32;
-
-        vb_entry[0].position[0] = particle.center.0 - radius;
-        vb_entry[0].position[1] = particle.center.1 + radius;
-        vb_entry[0].uv[0] = -1_f32;
-        vb_entry[0].uv[1] = -1_f32;
-
-        vb_entry[1].position[0] = particle.center.0 + radius;
-        vb_entry[1].position[1] = particle.center.1 + radius;
-        vb_entry[1].uv[0] = 1_f32;
-        vb_entry[1].uv[1] = -1_f32;
-
-        vb_entry[2].position[0] = particle.center.0 - radius;
-        vb_entry[2].position[1] = particle.center.1 - radius;
-        vb_entry[2].uv[0] = -1_f32;
-        vb_entry[2].uv[1] = 1_f32;
-
-        vb_entry[3].position[0] = particle.center.0 + radius;
-        vb_entry[3].position[1] = particle.center.1 - radius;
-        vb_entry[3].uv[0] = 1_f32;
-        vb_entry[3].uv[1] = 1_f32;
-
-        for entry in vb_entry.iter_mut().take(4) {
-            entry.color = [particle.color.0, particle.color.1, particle.color.2];
-            entry.blend_value = particle.opacity;
+impl Particle {
+    pub fn new(
+        center: (f32, f32),
+        scale: f32,
+        velocity: (f32, f32),
+        lifetime: f32,
+        behaviour: Behaviour,
+        collision: Option<Collision>,
+        emitter_id: usize,
+    ) -> Particle {
+        Particle {
+            center,
+            scale,
+            velocity,
+            lifetime,
+            behaviour,
+            collision,
+            emitter_id,
         }
-
-        generate_index_for_quad(i, index_buffer_data);
     }
 }
 
-fn get_dt(particle: &Particle) -> f32 {
-    particle.timer.time_delta / 1000_f32
-}
-fn get_distance_to_target(particle: &Particle) -> (f32, f32) {
-    (
-        particle.target.center.0 - particle.center.0,
-        particle.target.center.1 - particle.center.1,
-    )
+//the simulated state of a single particle, borrowed from the ParticleSystem arrays
+struct ParticleMut<'a> {
+    center: &'a mut (f32, f32),
+    velocity: &'a mut (f32, f32),
+    age: &'a mut f32,
+    stuck: &'a mut bool,
+    lifetime: f32,
+    behaviour: &'a Behaviour,
+    collision: &'a Option<Collision>,
 }
 
-fn check_in_ellipsis(particle: &Particle) -> bool {
-    let dx = particle.target.center.0 - particle.center.0;
-    let dy = particle.target.center.1 - particle.center.1;
-
-    (dx * dx) / (particle.target.size.0 * particle.target.size.0)
-        + (dy * dy) / (particle.target.size.1 * particle.target.size.1)
-        <= 1_f32
+//struct of arrays storage for all particles. Particles are stored in spawn order,
+//so the oldest particles are always at the front. Colors and sizes over the lifetime
+//are looked up in the emitter that spawned the particle
+pub struct ParticleSystem {
+    centers: Vec<(f32, f32)>,
+    velocities: Vec<(f32, f32)>,
+    scales: Vec<f32>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+    behaviours: Vec<Behaviour>,
+    collisions: Vec<Option<Collision>>,
+    stuck: Vec<bool>,
+    emitter_ids: Vec<usize>,
+    alive: Vec<bool>,
+    emitters: Vec<EmitterSettings>,
+    emitter_names: HashMap<String, usize>,
+    collision_field: Option<CollisionField>,
+    settings: ParticleSystemSettings,
 }
 
 fn magnitude(vector: (f32, f32)) -> f32 {
     (vector.0 * vector.0 + vector.1 * vector.1).sqrt()
 }
 
-fn update_particle_based_on_acceleration(
-    particle: &mut Particle,
+fn get_distance_to_target(center: (f32, f32), target: &Target) -> (f32, f32) {
+    (target.center.0 - center.0, target.center.1 - center.1)
+}
+
+fn check_in_ellipsis(center: (f32, f32), target: &Target) -> bool {
+    let (dx, dy) = get_distance_to_target(center, target);
+
+    (dx * dx) / (target.size.0 * target.size.0) + (dy * dy) / (target.size.1 * target.size.1)
+        <= 1_f32
+}
+
+fn update_based_on_acceleration(
+    center: &mut (f32, f32),
+    velocity: &mut (f32, f32),
     dv: (f32, f32),
     dt: f32,
     drag: f32,
 ) {
-    particle.velocity.0 = particle.velocity.0 * drag + dv.0 * dt;
-    particle.velocity.1 = particle.velocity.1 * drag + dv.1 * dt;
+    velocity.0 = velocity.0 * drag + dv.0 * dt;
+    velocity.1 = velocity.1 * drag + dv.1 * dt;
 
-    particle.center.0 += particle.velocity.0 * dt;
-    particle.center.1 += particle.velocity.1 * dt;
+    center.0 += velocity.0 * dt;
+    center.1 += velocity.1 * dt;
 }
 
-pub fn update_gravity_particle(particle: &mut Particle) {
-    let dt = get_dt(particle);
-
-    if check_in_ellipsis(particle) {
-        particle.state = ParticleState::ToRemove;
+//moves a particle according to its behaviour. Returns if the particle is still alive
+fn apply_behaviour(
+    center: &mut (f32, f32),
+    velocity: &mut (f32, f32),
+    behaviour: &Behaviour,
+    dt: f32,
+) -> bool {
+    match behaviour {
+        Behaviour::Gravity(target) => {
+            let alive = !check_in_ellipsis(*center, target);
+            let dx = get_distance_to_target(*center, target);
+            let magnitude_dx = magnitude(dx);
+            let dv = (
+                dx.0 * target.gravity / magnitude_dx,
+                dx.1 * target.gravity / magnitude_dx,
+            );
+            update_based_on_acceleration(center, velocity, dv, dt, 0.95);
+            alive
+        }
+        Behaviour::Linear(target) => {
+            center.0 += velocity.0 * dt;
+            center.1 += velocity.1 * dt;
+            //signed distance function without normalization
+            //this is the distance between the center and a line though the target,
+            //perpendicular to velocity
+            let d_x = velocity.1 * (target.center.1 - center.1);
+            let d_y = -velocity.0 * (target.center.0 - center.0);
+            d_x - d_y >= 0_f32
+        }
+        Behaviour::Repulsed(target) => {
+            let dx = get_distance_to_target(*center, target);
+            let mag = magnitude(dx);
+            //screen goes from -1 to 1, thus the diagonal is sqrt(2*2 + 2*2)
+            let alive = mag <= 8_f32.sqrt();
+            let dv = (-dx.0 * target.gravity / mag, -dx.1 * target.gravity / mag);
+            update_based_on_acceleration(center, velocity, dv, dt, 1.0);
+            alive
+        }
     }
-    let dx = get_distance_to_target(particle);
-    let magnitude_dx = magnitude(dx);
-    let dv = (
-        dx.0 * particle.target.gravity / magnitude_dx,
-        dx.1 * particle.target.gravity / magnitude_dx,
-    );
-    update_particle_based_on_acceleration(particle, dv, dt, 0.95);
 }
 
-pub fn update_linear_particle(particle: &mut Particle) {
-    let dt = get_dt(particle);
-
-    particle.center.0 += particle.velocity.0 * dt;
-    particle.center.1 += particle.velocity.1 * dt;
-    particle.opacity = (particle.opacity - dt).clamp(0_f32, 1_f32);
-    //signed distance function without normalization
-    //this is the distance between the center and a line though the target,
-    //perpendicular to velocity
-    let d_x = particle.velocity.1 * (particle.target.center.1 - particle.center.1);
-    let d_y = -particle.velocity.0 * (particle.target.center.0 - particle.center.0);
-    if d_x - d_y < 0_f32 || particle.opacity == 0_f32 {
-        particle.state = ParticleState::ToRemove;
+//advances a single particle by dt seconds. Returns if the particle is still alive
+fn update_particle(particle: ParticleMut, dt: f32, field: Option<&CollisionField>) -> bool {
+    *particle.age += dt;
+    if *particle.age >= particle.lifetime {
+        return false;
+    }
+    //stuck particles stay where they are until their lifetime is over
+    if *particle.stuck {
+        return true;
+    }
+    let previous = *particle.center;
+    let alive = apply_behaviour(particle.center, particle.velocity, particle.behaviour, dt);
+    if let (Some(collision), Some(field)) = (particle.collision, field) {
+        collide(
+            field,
+            *collision,
+            previous,
+            particle.center,
+            particle.velocity,
+            particle.stuck,
+            dt,
+        );
     }
+    alive
 }
 
-pub fn update_repulsed_particle(particle: &mut Particle) {
-    let dt = get_dt(particle);
-    let dx = get_distance_to_target(particle);
-    let mag = magnitude(dx);
+//removes all values whose particle is dead. retain visits elements in order,
+//so the spawn order is kept
+fn retain_alive<T>(values: &mut Vec<T>, alive: &[bool]) {
+    let mut alive = alive.iter();
+    values.retain(|_| *alive.next().unwrap_or(&false));
+}
 
-    //screen goes from -1 to 1, thus the diagonal is sqrt(2*2 + 2*2)
-    if mag > 8_f32.sqrt() {
-        particle.state = ParticleState::ToRemove;
+impl ParticleSystem {
+    pub fn new(
+        settings: ParticleSystemSettings,
+        emitters: &HashMap<String, EmitterSettings>,
+    ) -> ParticleSystem {
+        let capacity = settings.budget;
+        let mut emitter_names = HashMap::new();
+        let mut emitter_list = Vec::with_capacity(emitters.len());
+        for (name, emitter) in emitters {
+            emitter_names.insert(name.clone(), emitter_list.len());
+            emitter_list.push(emitter.clone());
+        }
+        ParticleSystem {
+            centers: Vec::with_capacity(capacity),
+            velocities: Vec::with_capacity(capacity),
+            scales: Vec::with_capacity(capacity),
+            ages: Vec::with_capacity(capacity),
+            lifetimes: Vec::with_capacity(capacity),
+            behaviours: Vec::with_capacity(capacity),
+            collisions: Vec::with_capacity(capacity),
+            stuck: Vec::with_capacity(capacity),
+            emitter_ids: Vec::with_capacity(capacity),
+            alive: Vec::with_capacity(capacity),
+            emitters: emitter_list,
+            emitter_names,
+            collision_field: None,
+            settings,
+        }
     }
 
-    let dv = (
-        -dx.0 * particle.target.gravity / mag,
-        -dx.1 * particle.target.gravity / mag,
-    );
-
-    update_particle_based_on_acceleration(particle, dv, dt, 1.0);
-}
+    fn len(&self) -> usize {
+        self.centers.len()
+    }
 
-fn get_random_point_in_area(point: (f32, f32), area: f32) -> (f32, f32) {
-    let mut randomizer = rng();
-    let r = randomizer.random_range(0_f32..area / 2_f32);
-    let phi = randomizer.random_range(0_f32..2_f32 * PI);
+    pub fn clear(&mut self) {
+        self.evict_oldest(self.len());
+    }
 
-    let x = phi.cos() * r + point.0;
-    let y = phi.sin() * r + point.1;
-    (x, y)
-}
+    fn evict_oldest(&mut self, number: usize) {
+        self.centers.drain(..number);
+        self.velocities.drain(..number);
+        self.scales.drain(..number);
+        self.ages.drain(..number);
+        self.lifetimes.drain(..number);
+        self.behaviours.drain(..number);
+        self.collisions.drain(..number);
+        self.stuck.drain(..number);
+        self.emitter_ids.drain(..number);
+        self.alive.drain(..number);
+    }
 
-pub fn generate_random_particles_around_point(
-    point: (f32, f32),
-    area: f32,
-    target: Target,
-    max_initial_speed: f32,
-    opacity: f32,
-    color: (f32, f32, f32),
-    scale: f32,
-    number: usize,
-) -> Vec<Particle> {
-    let mut result: Vec<Particle> = Vec::with_capacity(number);
-    let mut randomizer = rng();
+    //adds particles. If the budget is exceeded the oldest particles are removed first
+    pub fn spawn(&mut self, particles: Vec<Particle>) {
+        let overflow = (self.len() + particles.len()).saturating_sub(self.settings.budget);
+        self.evict_oldest(overflow.min(self.len()));
+
+        let skip = particles.len().saturating_sub(self.settings.budget);
+        for particle in particles.into_iter().skip(skip) {
+            self.centers.push(particle.center);
+            self.velocities.push(particle.velocity);
+            self.scales.push(particle.scale);
+            self.ages.push(0_f32);
+            self.lifetimes.push(particle.lifetime);
+            self.behaviours.push(particle.behaviour);
+            self.collisions.push(particle.collision);
+            self.stuck.push(false);
+            self.emitter_ids.push(particle.emitter_id);
+            self.alive.push(true);
+        }
+    }
 
-    for _ in 0..number {
-        let q = get_random_point_in_area(point, area);
-        let v_0: (f32, f32) = (q.0 - point.0, q.1 - point.1);
-        let vary = randomizer.random_range(0.5_f32..1.3_f32);
-        let v_norm = vary * max_initial_speed / magnitude(v_0);
-        let size_vary = randomizer.random_range(0.5_f32..1.5_f32);
-        let particle = Particle::new(
-            q,
-            scale * size_vary,
-            color,
-            opacity,
-            (v_0.0 * v_norm, v_0.1 * v_norm),
-            target,
-            update_gravity_particle,
-        );
-        result.push(particle);
+    //spawns the particles of the named emitter at every position
+    pub fn emit(
+        &mut self,
+        emitter_name: &str,
+        positions: &[(f32, f32)],
+        scale: f32,
+        targets: &TargetPositions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let emitter_id = *self
+            .emitter_names
+            .get(emitter_name)
+            .ok_or(format!("unknown particle emitter {}", emitter_name))?;
+        let emitter = &self.emitters[emitter_id];
+        let particles = positions
+            .iter()
+            .flat_map(|position| emit_particles(emitter, emitter_id, *position, scale, targets))
+            .collect();
+        self.spawn(particles);
+        Ok(())
     }
-    result
-}
 
-pub fn generate_random_repulsed_particles_around_point(
-    point: (f32, f32),
-    area: f32,
-    settings: &ParticleSetting,
-) -> Vec<Particle> {
-    let mut result: Vec<Particle> = Vec::with_capacity(settings.number);
-    let mut randomizer = rng();
+    pub fn emit_event(
+        &mut self,
+        events: &EventEmitters,
+        event: ParticleEvent,
+        positions: &[(f32, f32)],
+        scale: f32,
+        targets: &TargetPositions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match events.emitters.get(&event) {
+            Some(emitter_name) => self.emit(emitter_name, positions, scale, targets),
+            None => Ok(()),
+        }
+    }
 
-    for _ in 0..settings.number {
-        let q = get_random_point_in_area(point, area);
-        let target = Target {
-            center: point,
-            gravity: 1_f32,
-            size: (1_f32, 1_f32),
-        };
-        let v_0 = (q.0 - point.0, q.1 - point.1);
-        let vary = randomizer.random_range(0.5_f32..1.0_f32);
-        let size_vary = randomizer.random_range(0.5_f32..1.5_f32);
-        let v_norm = vary * settings.initial_velocity / magnitude(v_0);
-        let particle = Particle::new(
-            q,
-            settings.scale * size_vary,
-            settings.color,
-            settings.opacity,
-            (v_0.0 * v_norm, v_0.1 * v_norm),
-            target,
-            update_repulsed_particle,
-        );
-        result.push(particle);
+    //the collision field is only needed if an emitter spawns colliding particles
+    pub fn update_collision_field(&mut self, mask: &Mat) -> Result<(), Box<dyn std::error::Error>> {
+        if self
+            .emitters
+            .iter()
+            .any(|emitter| emitter.collision.is_some())
+        {
+            self.collision_field = Some(CollisionField::from_mask(mask)?);
+        }
+        Ok(())
     }
-    result
-}
 
-pub fn spawn_particles_for_skull(
-    pos: (f32, f32),
-    skull_scale: f32,
-    target_pos: (f32, f32),
-    target_scale: (f32, f32),
-    settings: &ParticleSetting,
-) -> Vec<Particle> {
-    let target = Target {
-        center: target_pos,
-        gravity: 3.5,
-        size: target_scale,
-    };
-    generate_random_particles_around_point(
-        pos,
-        skull_scale,
-        target,
-        settings.initial_velocity,
-        settings.opacity,
-        settings.color,
-        settings.scale,
-        settings.number,
-    )
-}
-impl Particle {
-    pub fn new(
-        center: (f32, f32),
-        scale: f32,
-        color: (f32, f32, f32),
-        opacity: f32,
-        velocity: (f32, f32),
-        target: Target,
-        update_function: fn(&mut Particle),
-    ) -> Particle {
-        Particle {
-            color,
-            opacity,
-            center,
-            target,
-            scale,
-            velocity,
-            timer: TimeStep::new(),
-            state: ParticleState::Alive,
-            update_function,
+    //advances all particles by dt seconds and removes dead ones
+    pub fn update(&mut self, dt: f32) {
+        let field = self.collision_field.as_ref();
+        if self.settings.parallel_update && self.len() >= self.settings.parallel_threshold {
+            (
+                self.centers.par_iter_mut(),
+                self.velocities.par_iter_mut(),
+                self.ages.par_iter_mut(),
+                self.stuck.par_iter_mut(),
+                self.lifetimes.par_iter(),
+                self.behaviours.par_iter(),
+                self.collisions.par_iter(),
+                self.alive.par_iter_mut(),
+            )
+                .into_par_iter()
+                .for_each(
+                    |(center, velocity, age, stuck, lifetime, behaviour, collision, alive)| {
+                        let particle = ParticleMut {
+                            center,
+                            velocity,
+                            age,
+                            stuck,
+                            lifetime: *lifetime,
+                            behaviour,
+                            collision,
+                        };
+                        *alive = update_particle(particle, dt, field);
+                    },
+                );
+        } else {
+            for i in 0..self.len() {
+                let particle = ParticleMut {
+                    center: &mut self.centers[i],
+                    velocity: &mut self.velocities[i],
+                    age: &mut self.ages[i],
+                    stuck: &mut self.stuck[i],
+                    lifetime: self.lifetimes[i],
+                    behaviour: &self.behaviours[i],
+                    collision: &self.collisions[i],
+                };
+                self.alive[i] = update_particle(particle, dt, field);
+            }
         }
+        self.remove_dead();
     }
 
-    pub fn update(&mut self) {
-        self.timer.update();
-        (self.update_function)(self);
+    fn remove_dead(&mut self) {
+        retain_alive(&mut self.centers, &self.alive);
+        retain_alive(&mut self.velocities, &self.alive);
+        retain_alive(&mut self.scales, &self.alive);
+        retain_alive(&mut self.ages, &self.alive);
+        retain_alive(&mut self.lifetimes, &self.alive);
+        retain_alive(&mut self.behaviours, &self.alive);
+        retain_alive(&mut self.collisions, &self.alive);
+        retain_alive(&mut self.stuck, &self.alive);
+        retain_alive(&mut self.emitter_ids, &self.alive);
+        self.alive.retain(|alive| *alive);
+    }
+
+    pub fn fill_sprites(&self, sprites: &mut SpriteRenderer) {
+        sprites.clear();
+        for i in 0..self.len() {
+            let emitter = &self.emitters[self.emitter_ids[i]];
+            let life = self.ages[i] / self.lifetimes[i];
+            let scale = self.scales[i] * sample_size_over_life(emitter, life);
+            sprites.push(SpriteInstance {
+                center: [self.centers[i].0, self.centers[i].1],
+                scale: [scale, scale],
+                rotation: 0_f32,
+                color: sample_color_over_life(emitter, life),
+                texture_layer: 0_f32,
+            });
+        }
     }
 }
 
 pub struct ParticleData {
-    pub particle_vb: VertexBuffer<ParticleVertex>,
-    pub particle_idxb: IndexBuffer<u32>,
-    pub particles: Vec<Particle>,
+    pub sprites: SpriteRenderer,
+    pub system: ParticleSystem,
 }
 
-pub fn update_particle_state(
-    particles: Vec<Particle>,
+pub fn create_particle_data(
     display: &DisplayType,
+    settings: &ParticleSettings,
 ) -> Result<ParticleData, Box<dyn std::error::Error>> {
-    let count = particles.len();
-
-    let mut vb: glium::VertexBuffer<ParticleVertex> =
-        glium::VertexBuffer::empty_dynamic(display, count * 4)?;
-    let mut index_buffer_data: Vec<u32> = Vec::with_capacity(count * 6);
-    //we can't map over a Vertex buffer length 0
-    if count > 0 {
-        create_particle_vertex_buffer(&mut vb, &particles, &mut index_buffer_data);
-    }
-
-    let res_vec = particles
-        .into_iter()
-        .filter(|particle| !matches!(particle.state, ParticleState::ToRemove))
-        .collect();
-
-    let idxb: glium::IndexBuffer<u32> = glium::IndexBuffer::new(
-        display,
-        glium::index::PrimitiveType::TrianglesList,
-        &index_buffer_data,
-    )?;
-
     Ok(ParticleData {
-        particle_vb: vb,
-        particle_idxb: idxb,
-        particles: res_vec,
+        sprites: SpriteRenderer::new(display, settings.system.budget)?,
+        system: ParticleSystem::new(settings.system.clone(), &settings.emitters),
     })
 }
+
+pub fn update_particle_state(
+    particle_data: &mut ParticleData,
+    display: &DisplayType,
+) -> Result<(), Box<dyn std::error::Error>> {
+    particle_data
+        .system
+        .fill_sprites(&mut particle_data.sprites);
+    particle_data.sprites.upload(display)
+}
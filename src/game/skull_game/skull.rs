@@ -2,11 +2,9 @@ use opencv::{Result, core::Range, prelude::*};
 use rand::{Rng, rng};
 
 use crate::display::display_window::DisplayType;
-use crate::game::skull_game::util::generate_index_for_quad;
+use crate::display::sprite::{SpriteInstance, SpriteRenderer};
 use crate::{display::timestep::TimeStep, game::skull_game::config::SkullSettings};
 
-use ::glium::{IndexBuffer, VertexBuffer};
-use glium::implement_vertex;
 use noise::{NoiseFn, Perlin};
 
 #[derive(Debug, Clone, Copy)]
@@ -35,114 +33,31 @@ pub struct Skull {
     pub noise: Perlin,
 }
 
-#[derive(Copy, Clone)]
-pub struct SkullVertex {
-    pub position: [f32; 2],
-    pub center: [f32; 2],
-    pub scale: f32,
-    pub uv: [f32; 2],
-    pub rotation: f32,
-    pub state: u32,
-    pub blend_value: f32,
-    pub flashing: f32,
-    pub texture_id: f32,
-}
-
-implement_vertex!(
-    SkullVertex,
-    position,
-    center,
-    scale,
-    uv,
-    rotation,
-    state,
-    blend_value,
-    flashing,
-    texture_id
-);
+//skulls that are alive and dying skulls use different texture arrays, so they are drawn separately
+pub fn create_skull_instance(skull: &Skull) -> SpriteInstance {
+    let blend = (skull.scale / skull.hitable_from).clamp(0_f32, 1_f32);
+    let texture_layer = match skull.state {
+        SkullState::Incomming => (skull.timer.runtime / 50_f32) % 4_f32,
+        SkullState::Hitable => (skull.timer.runtime / 50_f32) % 4_f32,
+        _ => (skull.timer.runtime / 50_f32) % 2_f32,
+    };
+    let flashing =
+        matches!(skull.state, SkullState::Hitable) && skull.timer.runtime % 3_f32 == 0_f32;
+    let color = if flashing {
+        [1_f32, 1_f32, 1_f32, blend]
+    } else {
+        [blend, 1_f32 - blend, 0_f32, blend]
+    };
 
-fn skull_state_to_id(state: &SkullState) -> u32 {
-    match state {
-        SkullState::Incomming => 0,
-        SkullState::Hitable => 1,
-        SkullState::Killed => 2,
-        SkullState::Survived => 3,
-        SkullState::ToRemove => 4,
+    SpriteInstance {
+        center: [skull.center.0, skull.center.1],
+        scale: [skull.scale, skull.scale],
+        rotation: skull.rotation,
+        color,
+        texture_layer,
     }
 }
 
-pub fn create_skull_vertex_buffer(
-    skull_vb: &mut glium::VertexBuffer<SkullVertex>,
-    skulls: &Vec<Skull>,
-    index_buffer_data: &mut Vec<u32>,
-) {
-    for (i, (skull, vb_entry)) in skulls.iter().zip(skull_vb.map().chunks_mut(4)).enumerate() {
-        let radius = skull.scale / 2_f32;
-        let blend = (skull.scale / skull.hitable_from).clamp(0_f32, 1_f32);
-        let state_id = skull_state_to_id(&skull.state);
-        let texture_id = match skull.state {
-            SkullState::Incomming => (skull.timer.runtime / 50_f32) % 4_f32,
-            SkullState::Hitable => (skull.timer.runtime / 50_f32) % 4_f32,
-            _ => (skull.timer.runtime / 50_f32) % 2_f32,
-        };
-        let flashing = if skull.timer.runtime % 3_f32 == 0_f32 {
-            1_f32
-        } else {
-            0_f32
-        };
-
-        let update_vb =
-            |x: f32, y: f32, u: f32, v: f32, vb_entry: &mut [SkullVertex], idx: usize| {
-                vb_entry[idx].position[0] = x;
-                vb_entry[idx].position[1] = y;
-                vb_entry[idx].center[0] = skull.center.0;
-                vb_entry[idx].center[1] = skull.center.1;
-                vb_entry[idx].scale = skull.scale;
-                vb_entry[idx].uv[0] = u;
-                vb_entry[idx].uv[1] = v;
-                vb_entry[idx].rotation = skull.rotation;
-                vb_entry[idx].blend_value = blend;
-                vb_entry[idx].flashing = flashing;
-                vb_entry[idx].texture_id = texture_id;
-                vb_entry[idx].state = state_id;
-            };
-
-        update_vb(
-            skull.center.0 - radius,
-            skull.center.1 + radius,
-            0_f32,
-            0_f32,
-            vb_entry,
-            0,
-        );
-        update_vb(
-            skull.center.0 + radius,
-            skull.center.1 + radius,
-            1_f32,
-            0_f32,
-            vb_entry,
-            1,
-        );
-        update_vb(
-            skull.center.0 - radius,
-            skull.center.1 - radius,
-            0_f32,
-            1_f32,
-            vb_entry,
-            2,
-        );
-        update_vb(
-            skull.center.0 + radius,
-            skull.center.1 - radius,
-            1_f32,
-            1_f32,
-            vb_entry,
-            3,
-        );
-
-        generate_index_for_quad(i, index_buffer_data);
-    }
-}
 pub fn hit_test(skull: &Skull, mask: &Mat) -> Result<bool> {
     let dims = (mask.rows(), mask.cols());
     let bounding_box = get_bounding_box(skull, dims)?;
@@ -237,43 +152,43 @@ impl Skull {
 }
 
 pub struct SkullData {
-    pub skull_vb: VertexBuffer<SkullVertex>,
-    pub skull_idxb: IndexBuffer<u32>,
+    pub alive_sprites: SpriteRenderer,
+    pub killed_sprites: SpriteRenderer,
     pub skulls: Vec<Skull>,
 }
 
-pub fn update_skull_state(
-    skulls: Vec<Skull>,
+pub fn create_skull_data(
     display: &DisplayType,
+    capacity: usize,
 ) -> Result<SkullData, Box<dyn std::error::Error>> {
-    let skull_count = skulls.len();
-
-    let mut skull_vb: glium::VertexBuffer<SkullVertex> =
-        glium::VertexBuffer::empty_dynamic(display, skull_count * 4)?;
-    let mut index_buffer_data: Vec<u32> = Vec::with_capacity(skull_count * 6);
-    //we can't map over a Vertex buffer length 0
-    if skull_count > 0 {
-        create_skull_vertex_buffer(&mut skull_vb, &skulls, &mut index_buffer_data);
-    }
-
-    let skull_idxb: glium::IndexBuffer<u32> = glium::IndexBuffer::new(
-        display,
-        glium::index::PrimitiveType::TrianglesList,
-        &index_buffer_data,
-    )?;
-
-    let res_vec = skulls
-        .into_iter()
-        .filter(|skull| !matches!(skull.state, SkullState::ToRemove))
-        .collect();
-
     Ok(SkullData {
-        skull_vb,
-        skull_idxb,
-        skulls: res_vec,
+        alive_sprites: SpriteRenderer::new(display, capacity)?,
+        killed_sprites: SpriteRenderer::new(display, capacity)?,
+        skulls: Vec::with_capacity(capacity),
     })
 }
 
+pub fn update_skull_state(
+    skull_data: &mut SkullData,
+    display: &DisplayType,
+) -> Result<(), Box<dyn std::error::Error>> {
+    skull_data
+        .skulls
+        .retain(|skull| !matches!(skull.state, SkullState::ToRemove));
+
+    skull_data.alive_sprites.clear();
+    skull_data.killed_sprites.clear();
+    for skull in skull_data.skulls.iter() {
+        let instance = create_skull_instance(skull);
+        match skull.state {
+            SkullState::Incomming | SkullState::Hitable => skull_data.alive_sprites.push(instance),
+            _ => skull_data.killed_sprites.push(instance),
+        }
+    }
+    skull_data.alive_sprites.upload(display)?;
+    skull_data.killed_sprites.upload(display)
+}
+
 pub enum GameEvent {
     Killed { pos: (f32, f32), skull_scale: f32 },
     Escaped { pos: (f32, f32), scale: f32 },
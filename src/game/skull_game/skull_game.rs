@@ -1,24 +1,26 @@
 use crate::PlatoConfig;
 use crate::config::load_config;
-use crate::display::{display_window::DisplayType, timestep::TimeStep};
-use crate::game::load_shaders;
+use crate::display::{
+    display_window::{DisplayType, RenderTarget},
+    timestep::TimeStep,
+};
 use crate::game::skull_game::config::{DifficultySelector, GameSettings, valdiate_config};
+use crate::game::skull_game::emitter::ParticleEvent;
 use crate::game::skull_game::live_view::LiveViewData;
 use crate::game::skull_game::moon::{MoonData, create_moon_data, update_moon_data};
 use crate::game::skull_game::particle::{
-    ParticleData, generate_random_repulsed_particles_around_point, spawn_particles_for_skull,
-    update_particle_state,
+    ParticleData, create_particle_data, update_particle_state,
 };
-use crate::game::skull_game::position_visualization::spawn_based_on_mask;
+use crate::game::skull_game::position_visualization::sample_mask_positions;
 use crate::game::skull_game::skull::{
-    self, GameEvent, SkullData, SkullSpawner, update_skull_state,
+    self, GameEvent, SkullData, SkullSpawner, create_skull_data, update_skull_state,
 };
 use crate::game::skull_game::util::{
     get_boxed_opencv_error, get_draw_params, get_random_sound_name, load_texture,
 };
 use crate::game::skull_game::victory::VicotryData;
 use crate::game::sound::{AudioHandler, SoundType};
-use crate::game::util::load_rgb_image_as_texture;
+use crate::game::util::{WatchedProgram, load_rgb_image_as_texture};
 use crate::types::game_types::GameTrait;
 
 use opencv::prelude::*;
@@ -65,7 +67,7 @@ pub struct SkullGame {
     victory_data: Option<VicotryData>,
     sound: Option<AudioHandler>,
 
-    programs: HashMap<String, glium::Program>,
+    programs: HashMap<String, WatchedProgram>,
     texture_arrays: HashMap<String, Texture2dArray>,
     textures: HashMap<String, Texture2d>,
 
@@ -107,22 +109,21 @@ impl SkullGame {
         let moon_ref: &mut MoonData = self.moon_data.as_mut().ok_or("moon not defined")?;
         let sound_ref = self.sound.as_ref().ok_or("sound not initialized")?;
 
+        let events = &self.settings.particle_settings.events;
+
         //hit test
         match (&mut self.skull_data, &mut self.particle_data) {
             (Some(data), Some(particles)) => {
                 for skull in data.skulls.iter_mut() {
                     match skull.update(&self.mask, timestep)? {
                         Some(GameEvent::Killed { pos, skull_scale }) => {
-                            particles.particles.append(&mut spawn_particles_for_skull(
-                                pos,
+                            particles.system.emit_event(
+                                events,
+                                ParticleEvent::SkullKilled,
+                                &[pos],
                                 skull_scale,
-                                moon_ref.moon.current_position,
-                                (
-                                    1.2_f32 * moon_ref.moon.scale.0,
-                                    1.2_f32 * moon_ref.moon.scale.1,
-                                ),
-                                &self.settings.particle_settings.killed,
-                            ));
+                                &moon_ref.moon.get_target_positions(),
+                            )?;
                             moon_ref.moon.hit(self.difficultiy.player_damage);
 
                             sound_ref.play(
@@ -134,13 +135,13 @@ impl SkullGame {
                             )?;
                         }
                         Some(GameEvent::Escaped { pos, scale }) => {
-                            particles.particles.append(
-                                &mut generate_random_repulsed_particles_around_point(
-                                    pos,
-                                    scale,
-                                    &self.settings.particle_settings.escaped,
-                                ),
-                            );
+                            particles.system.emit_event(
+                                events,
+                                ParticleEvent::SkullEscaped,
+                                &[pos],
+                                scale,
+                                &moon_ref.moon.get_target_positions(),
+                            )?;
                             moon_ref.moon.heal(self.difficultiy.escape_penalty);
                             sound_ref.play(
                                 &get_random_sound_name(
@@ -153,9 +154,7 @@ impl SkullGame {
                         None => {}
                     }
                 }
-                for particle in particles.particles.iter_mut() {
-                    particle.update()
-                }
+                particles.system.update(timestep.time_delta / 1000_f32);
                 self.skull_spawner.maybe_spawn(&mut data.skulls, timestep);
                 Ok(())
             }
@@ -167,18 +166,19 @@ impl SkullGame {
 
     fn draw_live(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         params: &glium::DrawParameters,
         timestep: &TimeStep,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match (&mut self.live_view_data, &self.moon_data) {
             (Some(live), Some(moon)) => {
-                if let Some(mat) = live.live_view_texture.lock().unwrap().as_ref() {
+                if let Some(mat) = live.live_view_texture.texture() {
                     frame.draw(
                         &live.live_view_vb,
                         &live.live_view_ib,
                         &self.programs["live_program"],
-                        &uniform! { live_tex: mat, moon_texture: &self.textures["moon_texture"],
+                        &uniform! { live_tex: mat, live_is_color: live.live_view_texture.is_color(),
+                        moon_texture: &self.textures["moon_texture"],
                         clouds: &self.textures["clouds"], clouds2: &self.textures["clouds2"],
                         sky: &self.textures["sky"], moon_pos_u: moon.moon.get_position(),
                         moon_scale:moon.moon.scale, time: timestep.runtime*0.001 },
@@ -193,16 +193,15 @@ impl SkullGame {
 
     fn draw_moon(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         params: &glium::DrawParameters,
         timestep: &TimeStep,
         color_selector: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match &mut self.moon_data {
             Some(moon) => {
-                frame.draw(
-                    &moon.corona_vb,
-                    &moon.corona_idxb,
+                moon.corona_sprite.draw(
+                    frame,
                     &self.programs["corona_program"],
                     &uniform! {time: timestep.runtime / 1000_f32,
                     life: moon.moon.get_life_fraction(),
@@ -210,9 +209,8 @@ impl SkullGame {
                     params,
                 )?;
 
-                frame.draw(
-                    &moon.moon_vb,
-                    &moon.moon_idxb,
+                moon.moon_sprite.draw(
+                    frame,
                     &self.programs["moon_program"],
                     &uniform! {moon_texture: &self.textures["moon_texture"],
                     moon_mask: &self.textures["moon"],
@@ -228,7 +226,7 @@ impl SkullGame {
 
     fn draw_scenary(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         timestep: &TimeStep,
         round_counter: usize,
     ) -> Result<(), Box<dyn std::error::Error>> {
@@ -237,7 +235,7 @@ impl SkullGame {
         self.draw_moon(frame, &params, timestep, round_counter)
     }
 
-    fn draw_victory(&mut self, frame: &mut glium::Frame) -> Result<(), Box<dyn std::error::Error>> {
+    fn draw_victory(&mut self, frame: &mut RenderTarget) -> Result<(), Box<dyn std::error::Error>> {
         match &mut self.victory_data {
             Some(v_data) => Ok(frame.draw(
                 &v_data.vertex_buffer,
@@ -252,35 +250,40 @@ impl SkullGame {
 
     fn draw_particles(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         params: &glium::DrawParameters,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match &self.particle_data {
-            Some(particles) => Ok(frame.draw(
-                &particles.particle_vb,
-                &particles.particle_idxb,
+            Some(particles) => particles.sprites.draw(
+                frame,
                 &self.programs["particle_program"],
                 &glium::uniforms::EmptyUniforms,
-                &params,
-            )?),
+                params,
+            ),
             None => Err(get_boxed_opencv_error("Particle", 3)),
         }
     }
 
     fn draw_skulls(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         params: &glium::DrawParameters,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match &self.skull_data {
-            Some(skulls) => Ok(frame.draw(
-                &skulls.skull_vb,
-                &skulls.skull_idxb,
-                &self.programs["skull_program"],
-                &uniform! { tex: &self.texture_arrays["skull_alive_textures"],
-                tex_killed: &self.texture_arrays["skull_killed_textures"]},
-                &params,
-            )?),
+            Some(skulls) => {
+                skulls.alive_sprites.draw(
+                    frame,
+                    &self.programs["skull_program"],
+                    &uniform! { tex: &self.texture_arrays["skull_alive_textures"]},
+                    params,
+                )?;
+                skulls.killed_sprites.draw(
+                    frame,
+                    &self.programs["skull_program"],
+                    &uniform! { tex: &self.texture_arrays["skull_killed_textures"]},
+                    params,
+                )
+            }
             None => Err(get_boxed_opencv_error("Skull", 3)),
         }
     }
@@ -290,30 +293,36 @@ impl SkullGame {
         display: &DisplayType,
         time_step: f32,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.skull_data = Some(update_skull_state(
-            self.skull_data.as_ref().unwrap().skulls.clone(),
+        update_skull_state(
+            self.skull_data.as_mut().ok_or("skulls not initialized")?,
             display,
-        )?);
-
-        self.particle_data = Some(update_particle_state(
-            self.particle_data.as_ref().unwrap().particles.clone(),
+        )?;
+        update_particle_state(
+            self.particle_data
+                .as_mut()
+                .ok_or("particles not initialized")?,
             display,
-        )?);
-
-        self.moon_data = Some(update_moon_data(
-            self.moon_data.as_mut().unwrap(),
+        )?;
+        update_moon_data(
+            self.moon_data.as_mut().ok_or("moon not initialized")?,
             display,
             time_step,
-        )?);
-        Ok(())
+        )
     }
 
     fn handle_mask(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let (Some(mask), Some(particle_data)) = (&self.mask, &mut self.particle_data) {
-            if let Ok(mut motion_particles) =
-                spawn_based_on_mask(mask, self.settings.particle_settings.visualization.number)
-            {
-                particle_data.particles.append(&mut motion_particles);
+        let events = &self.settings.particle_settings.events;
+        if let (Some(mask), Some(particle_data), Some(moon_d)) =
+            (&self.mask, &mut self.particle_data, &self.moon_data)
+        {
+            if let Ok(positions) = sample_mask_positions(mask, events.mask_motion_points) {
+                particle_data.system.emit_event(
+                    events,
+                    ParticleEvent::MaskMotion,
+                    &positions,
+                    1_f32,
+                    &moon_d.moon.get_target_positions(),
+                )?;
             }
         }
         Ok(())
@@ -326,19 +335,36 @@ impl SkullGame {
             self.particle_data.as_mut(),
         ) {
             for skull in skull_d.skulls.iter_mut() {
-                particle_d.particles.append(&mut spawn_particles_for_skull(
-                    skull.center,
+                particle_d.system.emit_event(
+                    &self.settings.particle_settings.events,
+                    ParticleEvent::SkullKilled,
+                    &[skull.center],
                     skull.scale,
-                    moon_d.moon.current_position,
-                    (1.2_f32 * moon_d.moon.scale.0, 1.2_f32 * moon_d.moon.scale.1),
-                    &self.settings.particle_settings.killed,
-                ));
+                    &moon_d.moon.get_target_positions(),
+                )?;
                 skull.state = skull::SkullState::Killed;
             }
             skull_d.skulls.clear();
         };
         Ok(())
     }
+
+    //for events that don't happen at a position of their own
+    fn emit_at_moon(&mut self, event: ParticleEvent) -> Result<(), Box<dyn std::error::Error>> {
+        if let (Some(moon_d), Some(particle_d)) =
+            (self.moon_data.as_ref(), self.particle_data.as_mut())
+        {
+            let targets = moon_d.moon.get_target_positions();
+            particle_d.system.emit_event(
+                &self.settings.particle_settings.events,
+                event,
+                &[targets.moon_center],
+                targets.moon_scale.0,
+                &targets,
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl GameTrait for SkullGame {
@@ -350,7 +376,7 @@ impl GameTrait for SkullGame {
         //load shaders
         let mut load_shader_helper =
             |name: String, path: String| -> Result<(), Box<dyn std::error::Error>> {
-                let program = load_shaders(&path, display)?;
+                let program = WatchedProgram::load(&path, display)?;
                 self.programs.insert(name, program);
                 Ok(())
             };
@@ -382,18 +408,18 @@ impl GameTrait for SkullGame {
         //create moon data
         self.moon_data = Some(create_moon_data(display, &self.settings.moon_settings)?);
         //create skull data
-        self.skull_data = Some(update_skull_state(
-            Vec::with_capacity(self.settings.skull_settings.max_number),
+        self.skull_data = Some(create_skull_data(
             display,
+            self.settings.skull_settings.max_number,
         )?);
 
         //get live view data
         self.live_view_data = Some(LiveViewData::generate_vertex_index_buffer(display)?);
 
         //create particle data
-        self.particle_data = Some(update_particle_state(
-            Vec::with_capacity(self.settings.skull_settings.max_number),
+        self.particle_data = Some(create_particle_data(
             display,
+            &self.settings.particle_settings,
         )?);
         self.victory_data = Some(VicotryData::new(display)?);
 
@@ -412,16 +438,19 @@ impl GameTrait for SkullGame {
         display: &DisplayType,
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.mask = Some(mask.clone());
+        if let Some(particle_data) = self.particle_data.as_mut() {
+            particle_data.system.update_collision_field(mask)?;
+        }
 
         if let Some(lv_ref) = self.live_view_data.as_mut() {
-            lv_ref.set_live_view_texture(display, image)?
+            lv_ref.set_live_view_texture(display, image, self.settings.live_view_mode)?
         };
         Ok(())
     }
 
     fn draw(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         display: &DisplayType,
         time_step: &TimeStep,
     ) -> Result<(), Box<dyn std::error::Error>> {
@@ -470,6 +499,10 @@ impl GameTrait for SkullGame {
                             sound_ref_mut.play("intermission", SoundType::Sfx)?;
                         };
                         self.kill_all_skulls()?;
+                        self.emit_at_moon(match *state {
+                            GameState::PostGame(_) => ParticleEvent::GameWon,
+                            _ => ParticleEvent::RoundWon,
+                        })?;
                     }
                 }
             }
@@ -478,9 +511,7 @@ impl GameTrait for SkullGame {
                 //just display moon healing press start key to continue
                 self.update_dynamic_buffers(display, time_step.time_delta)?;
                 if let Some(particles) = &mut self.particle_data {
-                    for particle in particles.particles.iter_mut() {
-                        particle.update();
-                    }
+                    particles.system.update(time_step.time_delta / 1000_f32);
                 }
                 self.draw_scenary(frame, time_step, (round_counter.round + 1) as usize)?;
                 self.draw_particles(frame, &params)?;
@@ -488,7 +519,11 @@ impl GameTrait for SkullGame {
             GameState::PostGame(round_counter) => {
                 let intro_over = sound_ref.get_duration_ms("finish".to_string())?
                     < round_counter.time_info.elapsed().as_millis() as f32;
+                if let Some(particles) = &mut self.particle_data {
+                    particles.system.update(time_step.time_delta / 1000_f32);
+                }
                 self.draw_scenary(frame, time_step, (round_counter.round) as usize)?;
+                self.draw_particles(frame, &params)?;
 
                 if intro_over {
                     self.draw_victory(frame)?;
@@ -510,11 +545,13 @@ impl GameTrait for SkullGame {
             }
         };
 
+        let mut round_started = false;
         if let Key::Character(val) = event.as_ref() {
             match &*state {
                 GameState::PreGame => {
                     if val.to_lowercase() == self.settings.key_settings.start_key {
                         *state = GameState::Game(RoundCounter::new(0, &self.settings));
+                        round_started = true;
                         if let Err(err) = play_start() {
                             println!("Error in playing sound {}. Continuing", err)
                         }
@@ -526,11 +563,17 @@ impl GameTrait for SkullGame {
                             round_counter.round + 1,
                             &self.settings,
                         ));
+                        round_started = true;
                     }
                 }
                 _ => {} //can;t start game in current state
             }
         };
+        drop(state);
+        let emitted = round_started.then(|| self.emit_at_moon(ParticleEvent::RoundStarted));
+        if let Some(Err(err)) = emitted {
+            eprintln!("could not emit round start particles {}", err);
+        }
 
         match event.as_ref() {
             Key::Character(val) if val == self.settings.key_settings.normal_mode_key => {
@@ -552,7 +595,7 @@ impl GameTrait for SkullGame {
         }
 
         if let Some(part_d) = self.particle_data.as_mut() {
-            part_d.particles.clear();
+            part_d.system.clear();
         }
 
         if let Some(skull_d) = self.skull_data.as_mut() {
@@ -565,4 +608,29 @@ impl GameTrait for SkullGame {
 
         *self.game_state.lock().unwrap() = GameState::PreGame;
     }
+
+    fn reload_shaders(&mut self, display: &DisplayType) {
+        for program in self.programs.values_mut() {
+            program.reload_if_changed(display);
+        }
+    }
+
+    fn status(&self) -> String {
+        let state = match *self.game_state.lock().unwrap() {
+            GameState::PreGame => "pre game".to_string(),
+            GameState::Game(round_counter) => format!(
+                "round {}/{}",
+                round_counter.round + 1,
+                round_counter.max_round
+            ),
+            GameState::Intermission(round_counter) => {
+                format!("intermission after round {}", round_counter.round + 1)
+            }
+            GameState::PostGame(_) => "post game".to_string(),
+        };
+        match &self.moon_data {
+            Some(moon_d) => format!("{} moon life {:.0}", state, moon_d.moon.life.current_value),
+            None => state,
+        }
+    }
 }
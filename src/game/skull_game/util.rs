@@ -10,16 +10,6 @@ use opencv::{Result, prelude::*};
 
 use rand::{Rng, rng};
 
-pub fn generate_index_for_quad(counter: usize, index_buffer_data: &mut Vec<u32>) {
-    let num = counter as u32;
-    index_buffer_data.push(num * 4);
-    index_buffer_data.push(num * 4 + 1);
-    index_buffer_data.push(num * 4 + 2);
-    index_buffer_data.push(num * 4 + 1);
-    index_buffer_data.push(num * 4 + 3);
-    index_buffer_data.push(num * 4 + 2);
-}
-
 pub fn get_boxed_opencv_error(name: &str, code: i32) -> Box<opencv::Error> {
     Box::new(opencv::Error {
         message: format!("{} data was not initialized", name).to_string(),
@@ -1,8 +1,14 @@
 use crate::config::{ShaderConfig, load_config};
 use crate::display::display_window::DisplayType;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
 use image::ImageReader;
-use opencv::imgproc::{COLOR_BGR2GRAY, cvt_color};
+use opencv::imgproc::{COLOR_BGR2GRAY, COLOR_BGR2RGB, COLOR_GRAY2RGB, cvt_color};
 use opencv::prelude::*;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::time::SystemTime;
 
 pub fn load_shaders(
     path: &str,
@@ -18,6 +24,61 @@ pub fn load_shaders(
     )?)
 }
 
+fn get_modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+//A shader program that remembers the file it was loaded from. It can be used everywhere
+//a glium::Program is expected and gets recompiled when the file changes
+pub struct WatchedProgram {
+    program: glium::Program,
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedProgram {
+    pub fn load(
+        path: &str,
+        display: &DisplayType,
+    ) -> Result<WatchedProgram, Box<dyn std::error::Error>> {
+        Ok(WatchedProgram {
+            program: load_shaders(path, display)?,
+            path: path.to_string(),
+            modified: get_modified_time(path),
+        })
+    }
+
+    //recompiles the program if the file changed since the last load. If compiling fails
+    //the old program is kept and the compile log is printed
+    pub fn reload_if_changed(&mut self, display: &DisplayType) {
+        let modified = get_modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match load_shaders(&self.path, display) {
+            Ok(program) => {
+                println!("reloaded shader {}", self.path);
+                self.program = program;
+            }
+            Err(err) => eprintln!(
+                "could not reload shader {}. Keeping the old one\n{}",
+                self.path, err
+            ),
+        }
+    }
+}
+
+impl Deref for WatchedProgram {
+    type Target = glium::Program;
+
+    fn deref(&self) -> &glium::Program {
+        &self.program
+    }
+}
+
 pub fn load_rgb_image_as_texture(
     path: &str,
     display: &DisplayType,
@@ -29,45 +90,184 @@ pub fn load_rgb_image_as_texture(
     Ok(glium::texture::Texture2d::new(display, image)?)
 }
 
-pub fn image_to_gray_texture_r(
-    display: &DisplayType,
-    mat: &Mat,
-) -> Result<glium::Texture2d, Box<dyn std::error::Error>> {
-    let mut gray_img = Mat::default();
-    match mat.channels() {
-        1 => {
-            gray_img = mat.clone();
+//selects if camera images are shown as gray or color images
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum LiveViewMode {
+    Gray,
+    Color,
+}
+
+//A texture that is kept alive between frames and updated in place, so a camera stream
+//doesn't allocate a new texture every frame. The image is uploaded as is (top row first),
+//so it is upside down for opengl. Draw it with get_stream_quad_buffer, which flips the
+//uv coordinates instead of the data.
+pub struct StreamTexture {
+    texture: Option<glium::Texture2d>,
+    pixel_buffer: Option<PixelBuffer<u8>>,
+    converted_img: Mat,
+    is_color: bool,
+}
+
+impl StreamTexture {
+    pub fn new() -> StreamTexture {
+        StreamTexture {
+            texture: None,
+            pixel_buffer: None,
+            converted_img: Mat::default(),
+            is_color: false,
         }
-        3 => {
-            cvt_color(
-                &mat,
-                &mut gray_img,
-                COLOR_BGR2GRAY,
-                1,
-                opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
-            )?;
+    }
+
+    pub fn texture(&self) -> Option<&glium::Texture2d> {
+        self.texture.as_ref()
+    }
+
+    //gray textures only fill the red channel, shaders use this to decide how to sample them
+    pub fn is_color(&self) -> bool {
+        self.is_color
+    }
+
+    pub fn upload(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+        mode: LiveViewMode,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match mode {
+            LiveViewMode::Gray => self.upload_gray(display, mat),
+            LiveViewMode::Color => self.upload_rgb(display, mat),
         }
-        _ => {
-            return Err("invalid matrix provided. Input needs to be either gray or BGR".into());
+    }
+
+    //uploads a gray or BGR image as a single red channel
+    pub fn upload_gray(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match mat.channels() {
+            1 => self.upload_r(display, mat),
+            3 => self.upload_converted(display, mat, COLOR_BGR2GRAY),
+            _ => Err("invalid matrix provided. Input needs to be either gray or BGR".into()),
         }
     }
-    mat_1c_to_texture_r(display, &gray_img)
-}
 
-pub fn mat_1c_to_texture_r(
-    display: &DisplayType,
-    mat: &Mat,
-) -> Result<glium::Texture2d, Box<dyn std::error::Error>> {
-    //we need to flip the image, because opengl is fucking retarded
-    let data: Vec<u8> = mat.data_bytes()?.iter().rev().copied().collect();
-    let text2d = glium::texture::RawImage2d {
-        data: std::borrow::Cow::from(data),
-        width: mat.cols() as u32,
-        height: mat.rows() as u32,
-        format: glium::texture::ClientFormat::U8,
-    };
-
-    Ok(glium::Texture2d::new(display, text2d)?)
+    //uploads a gray or BGR image as a RGB texture
+    pub fn upload_rgb(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match mat.channels() {
+            1 => self.upload_converted(display, mat, COLOR_GRAY2RGB),
+            3 => self.upload_converted(display, mat, COLOR_BGR2RGB),
+            _ => Err("invalid matrix provided. Input needs to be either gray or BGR".into()),
+        }
+    }
+
+    pub fn upload_r(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match mat.channels() {
+            1 => self.upload_raw(display, mat),
+            _ => Err("invalid matrix provided. Input needs to have a single channel".into()),
+        }
+    }
+
+    fn upload_converted(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+        conversion: i32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        cvt_color(
+            &mat,
+            &mut self.converted_img,
+            conversion,
+            0,
+            opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        let converted_img = std::mem::take(&mut self.converted_img);
+        let res = self.upload_raw(display, &converted_img);
+        self.converted_img = converted_img;
+        res
+    }
+
+    fn upload_raw(
+        &mut self,
+        display: &DisplayType,
+        mat: &Mat,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let is_color = mat.channels() == 3;
+        let (width, height) = (mat.cols() as u32, mat.rows() as u32);
+        let continuous;
+        let mat = match mat.is_continuous() {
+            true => mat,
+            false => {
+                continuous = mat.try_clone()?;
+                &continuous
+            }
+        };
+        let data = mat.data_bytes()?;
+
+        let texture = match self.texture.take() {
+            Some(texture)
+                if texture.dimensions() == (width, height) && self.is_color == is_color =>
+            {
+                texture
+            }
+            _ => {
+                self.pixel_buffer = Some(PixelBuffer::new_empty(display, data.len()));
+                self.is_color = is_color;
+                let format = match is_color {
+                    true => UncompressedFloatFormat::U8U8U8,
+                    false => UncompressedFloatFormat::U8,
+                };
+                glium::Texture2d::empty_with_format(
+                    display,
+                    format,
+                    MipmapsOption::NoMipmap,
+                    width,
+                    height,
+                )?
+            }
+        };
+
+        //pixel buffer uploads don't change the unpack alignment, so rows have to be 4 byte aligned.
+        //The pixel buffer is typed, so it's only used for single channel images
+        match self.pixel_buffer.as_ref() {
+            Some(pixel_buffer) if !is_color && width % 4 == 0 => {
+                pixel_buffer.write(data);
+                texture.main_level().raw_upload_from_pixel_buffer(
+                    pixel_buffer.as_slice(),
+                    0..width,
+                    0..height,
+                    0..1,
+                );
+            }
+            _ => texture.write(
+                glium::Rect {
+                    left: 0,
+                    bottom: 0,
+                    width,
+                    height,
+                },
+                glium::texture::RawImage2d {
+                    data: Cow::Borrowed(data),
+                    width,
+                    height,
+                    format: match is_color {
+                        true => glium::texture::ClientFormat::U8U8U8,
+                        false => glium::texture::ClientFormat::U8,
+                    },
+                },
+            ),
+        }
+        self.texture = Some(texture);
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone)]
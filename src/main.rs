@@ -5,43 +5,21 @@ mod game;
 mod threads;
 mod types;
 
-use crate::bg_subtract::{
-    BGSubtracSettings, MogSubtractor, NaiveSubtractor, OfSubtractor, TestSubtractor,
-};
-use crate::config::{PlatoConfig, load_config};
+use crate::bg_subtract::{BGSubtracSettings, load_script};
+use crate::config::{PlatoConfig, load_config, validate_config};
 use crate::display::start_display;
 use crate::game::{CalibrationGame, IdentityGame, SkullGame};
-use crate::threads::{bg_subtract_pipeline, camera_thread, validate_camera};
+use crate::threads::{CameraSource, bg_subtract_pipeline, camera_thread, validate_camera};
 use crate::types::{
-    BackgroundResult, BackgroundSubtractor, CameraMessage, CameraResult, GameTrait, GameType,
-    PipelineMessage, SubtractorType,
+    BackgroundResult, CameraMessage, CameraResult, GameTrait, GameType, PipelineMessage,
+    SubtractorType,
 };
 
-use opencv::prelude::*;
 use opencv::{Error, Result};
 
 use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
 use std::thread;
 
-fn create_bg_selector(
-    selected_type: SubtractorType,
-    settings: BGSubtracSettings,
-) -> Result<Box<dyn BackgroundSubtractor>> {
-    Ok(match selected_type {
-        SubtractorType::Mog => {
-            Box::new(MogSubtractor::new(settings.mog_settings)?) as Box<dyn BackgroundSubtractor>
-        }
-        SubtractorType::Naive => Box::new(NaiveSubtractor {
-            background_approximation: Mat::default(),
-            settings: settings.naive_settings,
-        }) as Box<dyn BackgroundSubtractor>,
-        SubtractorType::OpticalFlow => Box::new(OfSubtractor::new(settings.of_settings)?),
-        SubtractorType::Test => Box::new(TestSubtractor {
-            settings: settings.test_settings,
-        }),
-    })
-}
-
 fn create_game(
     selected_type: GameType,
 ) -> std::result::Result<Box<dyn GameTrait>, Box<dyn std::error::Error>> {
@@ -54,16 +32,29 @@ fn create_game(
 
 fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let config: PlatoConfig = load_config("config.toml")?;
+    validate_config(&config)?;
     let subtractor_config: BGSubtracSettings =
         load_config(&config.background_subtractor_config.settings_path)?;
-    let camera_index = config.camera_config.device_index;
-    print!("{:?}", camera_index);
-    let selector_type = config.background_subtractor_config.subtractor_type.clone();
+    let camera_config = config.camera_config.clone();
+    print!("{:?}", camera_config.device_index);
+    let bg_sub_config = config.background_subtractor_config.clone();
+    //the mouse and scripted subtractors create the mask themselves, so they work without a camera
+    let camera_source = match bg_sub_config.subtractor_type {
+        SubtractorType::Mouse => CameraSource::Blank(subtractor_config.mouse_settings.image_size),
+        SubtractorType::Scripted => CameraSource::Scripted(load_script(
+            &subtractor_config.scripted_settings.script_path,
+        )?),
+        _ => CameraSource::Device(camera_config.clone()),
+    };
 
     let game_type = config.game_type.clone();
     let game = create_game(game_type)?;
-    if validate_camera(camera_index).is_err() {
-        eprintln!("could not find camera at device idx {}", camera_index);
+    if matches!(camera_source, CameraSource::Device(_)) && validate_camera(&camera_config).is_err()
+    {
+        eprintln!(
+            "could not find camera at device idx {}",
+            camera_config.device_index
+        );
         return Err(Box::new(Error::new(2, "could not open camera")));
     }
 
@@ -83,7 +74,7 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     ) = sync_channel(1);
 
     let grab_handle =
-        thread::spawn(move || camera_thread(camera_control_receiver, image_sender, camera_index));
+        thread::spawn(move || camera_thread(camera_control_receiver, image_sender, camera_source));
 
     let pipeline_handle = thread::spawn(move || {
         bg_subtract_pipeline(
@@ -91,7 +82,8 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             image_receiver,
             pipeline_control_receiver,
             result_sender,
-            create_bg_selector(selector_type, subtractor_config)?,
+            bg_sub_config,
+            subtractor_config,
         )
     });
 
@@ -4,18 +4,118 @@ use opencv::prelude::*;
 use std::sync::mpsc::{Receiver, SyncSender};
 use std::time::SystemTime;
 
+use crate::bg_subtract::{
+    AutoReference, BGSubtracSettings, Calibration, ZoneMask, align_with_mask, create_subtractor,
+    filter_mask,
+};
+use crate::config::{BgSubConfig, update_config_value};
 use crate::threads::try_sending;
-use crate::types::BackgroundSubtractor;
 use crate::types::thread_types::*;
+use crate::types::{BackgroundSubtractor, SubtractorType};
+
+//The subtractor in use. Subtractors that were used before are kept, so switching back
+//to one keeps its tuned parameters
+struct SubtractorSelection {
+    settings: BGSubtracSettings,
+    active_type: SubtractorType,
+    active: Box<dyn BackgroundSubtractor>,
+    inactive: Vec<(SubtractorType, Box<dyn BackgroundSubtractor>)>,
+}
+
+impl SubtractorSelection {
+    fn new(
+        active_type: SubtractorType,
+        settings: BGSubtracSettings,
+    ) -> Result<SubtractorSelection> {
+        Ok(SubtractorSelection {
+            active: create_subtractor(&active_type, &settings)?,
+            active_type,
+            settings,
+            inactive: Vec::new(),
+        })
+    }
+
+    fn select(&mut self, selected_type: SubtractorType) -> Result<()> {
+        if selected_type == self.active_type {
+            return Ok(());
+        }
+        let selected = match self.inactive.iter().position(|(t, _)| *t == selected_type) {
+            Some(idx) => self.inactive.swap_remove(idx).1,
+            None => create_subtractor(&selected_type, &self.settings)?,
+        };
+        let previous = std::mem::replace(&mut self.active, selected);
+        let previous_type = std::mem::replace(&mut self.active_type, selected_type);
+        self.inactive.push((previous_type, previous));
+        Ok(())
+    }
+}
+
+//feeds the score of the last image to the calibration. Once it is done the threshold is
+//set and written back to the settings file. Returns true when the calibration is over
+fn calibrate(
+    calibration: &mut Calibration,
+    subtractor: &mut Box<dyn BackgroundSubtractor>,
+    settings_path: &str,
+) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+    let (section, parameter) = subtractor
+        .calibration_target()
+        .ok_or("the active subtractor can't be calibrated")?;
+    let Some(score) = subtractor.threshold_score() else {
+        return Ok(false);
+    };
+    let Some(threshold) = calibration.add_frame(&score)? else {
+        return Ok(false);
+    };
+    //two decimals are plenty and keep the settings file readable
+    let threshold = (threshold * 100.0).round() / 100.0;
+    subtractor.set_parameter(parameter, threshold)?;
+    update_config_value(settings_path, section, parameter, threshold)?;
+    println!(
+        "calibrated {} to {}, saved to {}",
+        parameter, threshold, settings_path
+    );
+    Ok(true)
+}
+
+fn save_model(
+    selection: &SubtractorSelection,
+    model_dir: &str,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(model_dir)?;
+    match selection.active.save_model(model_dir)? {
+        true => Ok(()),
+        false => Err("the active subtractor has no model that can be saved".into()),
+    }
+}
+
+//the confidence games get has to agree with the filtered and zoned mask
+fn final_confidence(
+    confidence: Option<Mat>,
+    mask: &Result<Mat>,
+    zones: &mut ZoneMask,
+) -> Option<Mat> {
+    let (Some(confidence), Ok(mask)) = (confidence, mask) else {
+        return None;
+    };
+    align_with_mask(&confidence, mask)
+        .and_then(|mut aligned| zones.apply(&mut aligned).map(|_| aligned))
+        .map_err(|error| eprintln!("could not match the confidence with the mask {error}"))
+        .ok()
+}
 
 fn compute_resulting_image(
     input_image: Mat,
-    subtractor: &mut Box<dyn BackgroundSubtractor>,
+    selection: &mut SubtractorSelection,
+    zones: &mut ZoneMask,
 ) -> Result<Mat> {
-    let res = subtractor.apply(input_image);
+    let res = selection.active.apply(input_image);
 
     match res {
-        Ok(res) => res.to_mat(),
+        Ok(res) => {
+            let mut mask = filter_mask(res.to_mat()?, &selection.settings.mask_filter)?;
+            zones.apply(&mut mask)?;
+            Ok(mask)
+        }
         Err(..) => Err(opencv::Error {
             code: 2,
             message: "Math error in compute function".to_string(),
@@ -28,17 +128,53 @@ pub fn bg_subtract_pipeline(
     image_grabbing_queue: Receiver<CameraResult>,
     pipeline_control_queue: Receiver<PipelineMessage>,
     result_queue: SyncSender<BackgroundResult>,
-    bg_subtractor: Box<dyn BackgroundSubtractor>,
+    config: BgSubConfig,
+    settings: BGSubtracSettings,
 ) -> Result<()> {
-    let mut subtractor = bg_subtractor;
+    let BgSubConfig {
+        subtractor_type,
+        settings_path,
+        zones_path,
+        model_dir,
+    } = config;
+    let mut zones = ZoneMask::new(&zones_path);
+    let calibration_settings = settings.calibration;
+    let mut auto_reference = AutoReference::new(settings.auto_reference);
+    let mut selection = SubtractorSelection::new(subtractor_type, settings)?;
+    let mut calibration: Option<Calibration> = None;
+    //a newly selected subtractor takes the next image as its reference. At startup a
+    //saved model is used instead, the first image may show people
+    let mut needs_reference = match selection.active.load_model(&model_dir) {
+        Ok(loaded) => {
+            if loaded {
+                println!(
+                    "loaded {:?} model from {}",
+                    selection.active_type, model_dir
+                );
+            }
+            !loaded
+        }
+        Err(error) => {
+            eprintln!("could not load model from {}: {}", model_dir, error);
+            true
+        }
+    };
+    //messages that don't consume an image must not query another one, or the camera
+    //thread blocks on the full image queue
+    let mut image_requested = false;
+    let mut collect_stages = false;
+    let mut parameter_changes = 0;
     loop {
         //always query an image
-        try_sending(
-            &camera_control_queue,
-            CameraMessage::GetImage,
-            "pipeline_thread",
-            "camera control queue",
-        );
+        if !image_requested {
+            try_sending(
+                &camera_control_queue,
+                CameraMessage::GetImage,
+                "pipeline_thread",
+                "camera control queue",
+            );
+            image_requested = true;
+        }
 
         //wait for msg from the window thread
         match pipeline_control_queue.recv() {
@@ -53,11 +189,14 @@ pub fn bg_subtract_pipeline(
                         );
                         //discard queried image. If we don't query before we know what happens we waste
                         //time, but here we have to discard one
-                        let _ = image_grabbing_queue.recv();
+                        if image_requested {
+                            let _ = image_grabbing_queue.recv();
+                        }
                         println!("Quitting pipeline gracefully");
                         return Ok(());
                     }
                     PipelineMessage::GenerateImage => {
+                        image_requested = false;
                         match image_grabbing_queue.recv() {
                             Err(error) => {
                                 eprintln!(
@@ -67,16 +206,54 @@ pub fn bg_subtract_pipeline(
                             Ok(result) => {
                                 let (input_image,output_image) = match result.data{
                             Ok(camera_res)=>{
-                        let output = compute_resulting_image(camera_res.clone(), &mut subtractor);
+                        if needs_reference {
+                            selection.active.reset(camera_res.clone());
+                            auto_reference.reference_set(&camera_res);
+                            needs_reference = false;
+                        }
+                        let output = compute_resulting_image(camera_res.clone(), &mut selection, &mut zones);
+                        if let Some(active) = calibration.as_mut() {
+                            match calibrate(active, &mut selection.active, &settings_path) {
+                                Ok(false) => (),
+                                Ok(true) => calibration = None,
+                                Err(error) => {
+                                    eprintln!("calibration failed {error}");
+                                    calibration = None;
+                                }
+                            }
+                        }
+                        //the calibration takes care of the reference itself
+                        let refreshable = calibration.is_none() && selection.active.takes_reference();
+                        if let (true, Ok(mask)) = (refreshable, output.as_ref()) {
+                            match auto_reference.check(&camera_res, mask) {
+                                Ok(true) => selection.active.reset(camera_res.clone()),
+                                Ok(false) => (),
+                                Err(error) => eprintln!("auto reference check failed {error}"),
+                            }
+                        }
                         (Ok(camera_res),output)
                             },
                             Err(e)=> (Err(e),Err(opencv::Error{message:"could not compute mask, since we got no input image".to_string(), code :1})),
                         };
+                                let mut channels = selection.active.channels();
+                                channels.confidence = final_confidence(
+                                    channels.confidence.take(),
+                                    &output_image,
+                                    &mut zones,
+                                );
                                 try_sending(
                                     &result_queue,
                                     BackgroundResult {
                                         mask: output_image,
                                         image: input_image,
+                                        stages: match collect_stages {
+                                            true => selection.active.stages(),
+                                            false => Vec::new(),
+                                        },
+                                        channels,
+                                        subtractor: selection.active_type.clone(),
+                                        parameters: selection.active.parameters(),
+                                        parameter_changes,
                                         timestamp: SystemTime::now(),
                                     },
                                     "pipeline thread",
@@ -85,19 +262,70 @@ pub fn bg_subtract_pipeline(
                             }
                         }
                     }
-                    PipelineMessage::SetReference => match image_grabbing_queue.recv() {
-                        Err(error) => {
-                            eprintln!(
-                                "receiver error (Pipeline thread, image_grabbing_queue) {error}"
-                            )
+                    PipelineMessage::Pointer(pointer) => selection.active.pointer_event(pointer),
+                    PipelineMessage::RenderedFrame(frame) => selection.active.rendered_frame(frame),
+                    PipelineMessage::CollectStages(collect) => collect_stages = collect,
+                    PipelineMessage::SetSubtractor(selected_type) => {
+                        match selection.select(selected_type) {
+                            Ok(()) => {
+                                println!("switched to {:?} subtractor", selection.active_type);
+                                needs_reference = true;
+                                //the scores of another subtractor don't mean anything
+                                if calibration.take().is_some() {
+                                    println!("calibration cancelled");
+                                }
+                            }
+                            Err(error) => eprintln!("could not switch subtractor {error}"),
+                        }
+                    }
+                    PipelineMessage::SetParameter(name, value) => {
+                        parameter_changes += 1;
+                        match selection.active.set_parameter(&name, value) {
+                            Ok(()) => println!("set {} to {}", name, value),
+                            Err(error) => eprintln!("could not set {}: {}", name, error),
+                        }
+                    }
+                    //the scores are relative to the reference, so it is taken from the empty scene
+                    PipelineMessage::Calibrate => {
+                        calibration = match calibration.take() {
+                            Some(mut active) => active.advance().then_some(active),
+                            None => match Calibration::new(calibration_settings) {
+                                Ok(started) => {
+                                    needs_reference = true;
+                                    Some(started)
+                                }
+                                Err(error) => {
+                                    eprintln!("could not start calibration {error}");
+                                    None
+                                }
+                            },
                         }
-                        Ok(result) => match result.data {
-                            Ok(image_data) => subtractor.reset(image_data),
-                            Err(error) => eprintln!(
-                                "receiver error (Pipeline thread, image_grabbing_queue. Could not set reference image.) {error}"
-                            ),
-                        },
+                    }
+                    PipelineMessage::SaveModel => match save_model(&selection, &model_dir) {
+                        Ok(()) => {
+                            println!("saved {:?} model to {}", selection.active_type, model_dir)
+                        }
+                        Err(error) => eprintln!("could not save model {error}"),
                     },
+                    PipelineMessage::SetReference => {
+                        image_requested = false;
+                        match image_grabbing_queue.recv() {
+                            Err(error) => {
+                                eprintln!(
+                                    "receiver error (Pipeline thread, image_grabbing_queue) {error}"
+                                )
+                            }
+                            Ok(result) => match result.data {
+                                Ok(image_data) => {
+                                    auto_reference.reference_set(&image_data);
+                                    selection.active.reset(image_data)
+                                }
+                                Err(error) => eprintln!(
+                                    "receiver error (Pipeline thread, image_grabbing_queue. Could not set reference image.) {error}"
+                                ),
+                            },
+                        }
+                    }
                 }
             }
             Err(error) => {
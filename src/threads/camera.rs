@@ -1,19 +1,200 @@
+use opencv::core::{CV_8UC3, Scalar};
 use opencv::prelude::*;
-use opencv::videoio::VideoCapture;
+use opencv::videoio::{VideoCapture, VideoWriter};
 use opencv::{Error, Result, videoio};
+use serde::Deserialize;
 use std::sync::mpsc::{Receiver, SyncSender};
 use std::time::SystemTime;
 
+use crate::bg_subtract::Script;
+use crate::config::CameraConfig;
 use crate::threads::try_sending;
 use crate::types::thread_types::*;
 
-fn get_camera(camera_index: i32) -> Result<VideoCapture> {
-    videoio::VideoCapture::new(camera_index, videoio::CAP_ANY)
+//the opencv backend the camera is opened with. Any lets opencv pick one
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum CameraBackend {
+    #[default]
+    Any,
+    V4l2,
+    DirectShow,
+    MediaFoundation,
+    AvFoundation,
+    GStreamer,
 }
 
-pub fn validate_camera(camera_index: i32) -> Result<()> {
-    let mut cam = get_camera(camera_index)?;
-    let res = cam.open(camera_index, videoio::CAP_ANY)?;
+impl CameraBackend {
+    fn api(self) -> i32 {
+        match self {
+            CameraBackend::Any => videoio::CAP_ANY,
+            CameraBackend::V4l2 => videoio::CAP_V4L2,
+            CameraBackend::DirectShow => videoio::CAP_DSHOW,
+            CameraBackend::MediaFoundation => videoio::CAP_MSMF,
+            CameraBackend::AvFoundation => videoio::CAP_AVFOUNDATION,
+            CameraBackend::GStreamer => videoio::CAP_GSTREAMER,
+        }
+    }
+}
+
+//where the images come from. Blank produces black images of the given size (width, height)
+//for subtractors that don't need a camera, Scripted renders a scripted scene frame by frame
+#[derive(Clone, Debug)]
+pub enum CameraSource {
+    Device(CameraConfig),
+    Blank((i32, i32)),
+    Scripted(Script),
+}
+
+enum Grabber {
+    Device(VideoCapture),
+    Blank(Mat),
+    Scripted { script: Script, frame: u64 },
+}
+
+impl Grabber {
+    fn open(source: CameraSource) -> Result<Grabber> {
+        Ok(match source {
+            CameraSource::Device(config) => Grabber::Device(get_camera(&config)?),
+            CameraSource::Blank((width, height)) => Grabber::Blank(
+                Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(0.0))?,
+            ),
+            CameraSource::Scripted(script) => Grabber::Scripted { script, frame: 0 },
+        })
+    }
+
+    fn read(&mut self) -> Result<Mat> {
+        match self {
+            Grabber::Device(cam) => {
+                let mut frame = Mat::default();
+                cam.read(&mut frame)?;
+                Ok(frame)
+            }
+            Grabber::Blank(image) => Ok(image.clone()),
+            Grabber::Scripted { script, frame } => {
+                let image = script.render(script.frame_time(*frame));
+                *frame += 1;
+                image
+            }
+        }
+    }
+}
+
+fn fourcc_code(fourcc: &str) -> Result<i32> {
+    match fourcc.chars().collect::<Vec<char>>()[..] {
+        [c1, c2, c3, c4] => VideoWriter::fourcc(c1, c2, c3, c4),
+        _ => Err(Error {
+            code: -5,
+            message: format!("fourcc {} doesn't have four characters", fourcc),
+        }),
+    }
+}
+
+fn fourcc_name(code: f64) -> String {
+    (code as u32)
+        .to_le_bytes()
+        .iter()
+        .map(|byte| *byte as char)
+        .collect()
+}
+
+//the driver may ignore a property or round it to what the device supports, the accepted
+//values are reported afterwards
+fn set_property(cam: &mut VideoCapture, name: &str, prop_id: i32, value: f64) -> Result<()> {
+    if !cam.set(prop_id, value)? {
+        eprintln!("camera doesn't support setting {} to {}", name, value);
+    }
+    Ok(())
+}
+
+//properties that aren't configured keep the driver defaults. The fourcc has to come before
+//the resolution, some drivers only offer high resolutions with compressed formats
+fn apply_properties(cam: &mut VideoCapture, config: &CameraConfig) -> Result<()> {
+    if let Some(fourcc) = config.fourcc.as_ref() {
+        set_property(
+            cam,
+            "fourcc",
+            videoio::CAP_PROP_FOURCC,
+            fourcc_code(fourcc)? as f64,
+        )?;
+    }
+    let properties = [
+        (
+            "width",
+            videoio::CAP_PROP_FRAME_WIDTH,
+            config.width.map(f64::from),
+        ),
+        (
+            "height",
+            videoio::CAP_PROP_FRAME_HEIGHT,
+            config.height.map(f64::from),
+        ),
+        ("fps", videoio::CAP_PROP_FPS, config.fps),
+        ("gain", videoio::CAP_PROP_GAIN, config.gain),
+        (
+            "autofocus",
+            videoio::CAP_PROP_AUTOFOCUS,
+            config.autofocus.map(|on| on as i32 as f64),
+        ),
+    ];
+    for (name, prop_id, value) in properties {
+        if let Some(value) = value {
+            set_property(cam, name, prop_id, value)?;
+        }
+    }
+    //v4l2 takes 1 for manual exposure, the other backends follow directshow with 0.25
+    if let Some(exposure) = config.exposure {
+        let manual = match cam.get_backend_name()?.as_str() {
+            "V4L2" => 1.0,
+            _ => 0.25,
+        };
+        set_property(
+            cam,
+            "auto exposure",
+            videoio::CAP_PROP_AUTO_EXPOSURE,
+            manual,
+        )?;
+        set_property(cam, "exposure", videoio::CAP_PROP_EXPOSURE, exposure)?;
+    }
+    if let Some(temperature) = config.white_balance {
+        set_property(cam, "auto white balance", videoio::CAP_PROP_AUTO_WB, 0.0)?;
+        set_property(
+            cam,
+            "white balance",
+            videoio::CAP_PROP_WB_TEMPERATURE,
+            temperature,
+        )?;
+    }
+    Ok(())
+}
+
+fn report_properties(cam: &VideoCapture) -> Result<()> {
+    println!(
+        "camera opened with {}: {}x{} {} at {} fps, exposure {} (auto {}), gain {}, white balance {} (auto {}), autofocus {}",
+        cam.get_backend_name()?,
+        cam.get(videoio::CAP_PROP_FRAME_WIDTH)?,
+        cam.get(videoio::CAP_PROP_FRAME_HEIGHT)?,
+        fourcc_name(cam.get(videoio::CAP_PROP_FOURCC)?),
+        cam.get(videoio::CAP_PROP_FPS)?,
+        cam.get(videoio::CAP_PROP_EXPOSURE)?,
+        cam.get(videoio::CAP_PROP_AUTO_EXPOSURE)?,
+        cam.get(videoio::CAP_PROP_GAIN)?,
+        cam.get(videoio::CAP_PROP_WB_TEMPERATURE)?,
+        cam.get(videoio::CAP_PROP_AUTO_WB)?,
+        cam.get(videoio::CAP_PROP_AUTOFOCUS)?,
+    );
+    Ok(())
+}
+
+fn get_camera(config: &CameraConfig) -> Result<VideoCapture> {
+    let mut cam = VideoCapture::new(config.device_index, config.backend.api())?;
+    apply_properties(&mut cam, config)?;
+    report_properties(&cam)?;
+    Ok(cam)
+}
+
+pub fn validate_camera(config: &CameraConfig) -> Result<()> {
+    let mut cam = VideoCapture::new(config.device_index, config.backend.api())?;
+    let res = cam.is_opened()?;
     let _ = cam.release();
 
     match res {
@@ -28,9 +209,9 @@ pub fn validate_camera(camera_index: i32) -> Result<()> {
 pub fn camera_thread(
     camera_controller_queue: Receiver<CameraMessage>,
     image_queue: SyncSender<CameraResult>,
-    camera_index: i32,
+    source: CameraSource,
 ) -> Result<()> {
-    let mut cam = get_camera(camera_index)?;
+    let mut grabber = Grabber::open(source)?;
     loop {
         match camera_controller_queue.recv() {
             Ok(msg) => match msg {
@@ -39,12 +220,7 @@ pub fn camera_thread(
                     return Ok(());
                 }
                 CameraMessage::GetImage => {
-                    let mut frame = Mat::default();
-                    let camera_result = cam.read(&mut frame);
-                    let image = match camera_result {
-                        Err(e) => Err(e),
-                        Ok(..) => Ok(frame),
-                    };
+                    let image = grabber.read();
 
                     try_sending(
                         &image_queue,
@@ -2,15 +2,136 @@ use opencv::Result;
 use opencv::core::{Mat, MatExpr};
 use serde::Deserialize;
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug, PartialEq)]
 pub enum SubtractorType {
     Naive,
     Mog,
     OpticalFlow,
     Test,
+    Mouse,
+    Scripted,
+    Shadow,
+    Chroma,
+    MotionHistory,
+    Ensemble,
+}
+
+//order in which the subtractors are cycled at runtime. Scripted only makes sense with
+//its own fake camera, so it can only be selected at startup
+pub fn next_subtractor(current: &SubtractorType) -> SubtractorType {
+    match current {
+        SubtractorType::Naive => SubtractorType::Mog,
+        SubtractorType::Mog => SubtractorType::OpticalFlow,
+        SubtractorType::OpticalFlow => SubtractorType::Shadow,
+        SubtractorType::Shadow => SubtractorType::Chroma,
+        SubtractorType::Chroma => SubtractorType::MotionHistory,
+        SubtractorType::MotionHistory => SubtractorType::Ensemble,
+        SubtractorType::Ensemble => SubtractorType::Test,
+        SubtractorType::Test => SubtractorType::Mouse,
+        SubtractorType::Mouse | SubtractorType::Scripted => SubtractorType::Naive,
+    }
+}
+
+//a setting of a subtractor that can be changed while running. step is how much a
+//single key press changes it
+#[derive(Clone, Debug)]
+pub struct SubtractorParameter {
+    pub name: String,
+    pub value: f64,
+    pub step: f64,
+}
+
+impl SubtractorParameter {
+    pub fn new(name: &str, value: f64, step: f64) -> SubtractorParameter {
+        SubtractorParameter {
+            name: name.to_string(),
+            value,
+            step,
+        }
+    }
+}
+
+//pointer input of the projector window, for subtractors that are driven by the mouse.
+//position is in opengl coordinates
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PointerState {
+    pub position: (f32, f32),
+    pub pressed: bool,
+    pub stamp: bool,
+}
+
+//per pixel information besides the mask, in camera coordinates like the mask. Only some
+//subtractors provide it
+#[derive(Debug, Default)]
+pub struct MaskChannels {
+    //seconds since the last motion, CV_32F
+    pub motion_age: Option<Mat>,
+    //how sure the subtractor is that a pixel is a silhouette, CV_32F between 0 and 1.
+    //The mask is where it's above 0.5, so it can be used for feathered edges
+    pub confidence: Option<Mat>,
 }
 
 pub trait BackgroundSubtractor {
     fn apply(&mut self, input_img: Mat) -> Result<MatExpr>;
     fn reset(&mut self, background_img: Mat);
+
+    //true if reset builds the background from the given image. Only then refreshing the
+    //reference while the scene is empty helps, other subtractors just lose what they learned
+    fn takes_reference(&self) -> bool {
+        false
+    }
+
+    //intermediate images of the last apply call, so the operator can inspect them.
+    //they are moved out, so every stage is only handed out once
+    fn stages(&mut self) -> Vec<(String, Mat)> {
+        Vec::new()
+    }
+
+    fn channels(&mut self) -> MaskChannels {
+        MaskChannels::default()
+    }
+
+    //only subtractors that don't use the camera care about the mouse
+    fn pointer_event(&mut self, _pointer: PointerState) {}
+
+    //what the renderer just drew, read back from opengl as RGBA. Only sent to
+    //subtractors that look at the projection
+    fn rendered_frame(&mut self, _frame: Mat) {}
+
+    //settings section and name of the threshold the automatic calibration chooses. None
+    //if the subtractor can't be calibrated
+    fn calibration_target(&self) -> Option<(&'static str, &'static str)> {
+        None
+    }
+
+    //what the threshold was compared with in the last apply call, None while there
+    //is nothing to compare yet
+    fn threshold_score(&self) -> Option<Mat> {
+        None
+    }
+
+    //settings that can be tuned at runtime with their current values
+    fn parameters(&self) -> Vec<SubtractorParameter> {
+        Vec::new()
+    }
+
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        _value: f64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err(format!("unknown parameter {}", name).into())
+    }
+
+    //writes the background model into dir, so it survives a restart. Ok(false) if the
+    //subtractor has no model to save
+    fn save_model(&self, _dir: &str) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        Ok(false)
+    }
+
+    //loads what save_model wrote. Ok(false) if there is no saved model, then the
+    //subtractor needs a reference as usual
+    fn load_model(&mut self, _dir: &str) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        Ok(false)
+    }
 }
@@ -1,7 +1,11 @@
 use crate::{
-    config::PlatoConfig, display::display_window::DisplayType, display::timestep::TimeStep,
+    config::PlatoConfig,
+    display::display_window::{DisplayType, RenderTarget},
+    display::timestep::TimeStep,
 };
 
+use crate::types::{MaskChannels, PointerState};
+
 use glium::winit::keyboard::Key;
 use opencv::prelude::*;
 use serde::Deserialize;
@@ -29,14 +33,34 @@ pub trait GameTrait {
         Ok(())
     }
 
+    //extra channels of subtractors that have them, see MaskChannels. Called after update
+    fn update_channels(
+        &mut self,
+        _channels: &MaskChannels,
+        _display: &DisplayType,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
     fn draw(
         &mut self,
-        frame: &mut glium::Frame,
+        frame: &mut RenderTarget,
         display: &DisplayType,
         timestep: &TimeStep,
     ) -> Result<(), Box<dyn std::error::Error>>;
 
     fn key_event(&mut self, _event: &Key) {}
 
+    //cursor and left mouse button on the projector, position in opengl coordinates
+    fn pointer_event(&mut self, _pointer: &PointerState) {}
+
     fn reset(&mut self) {}
+
+    //recompiles shaders whose files changed
+    fn reload_shaders(&mut self, _display: &DisplayType) {}
+
+    //short description of the current game state, shown in the operator window
+    fn status(&self) -> String {
+        String::new()
+    }
 }
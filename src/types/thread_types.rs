@@ -2,6 +2,8 @@ use opencv::Result;
 use opencv::core::Mat;
 use std::time::SystemTime;
 
+use crate::types::{MaskChannels, PointerState, SubtractorParameter, SubtractorType};
+
 pub enum CameraMessage {
     GetImage,
     Quit,
@@ -10,6 +12,17 @@ pub enum CameraMessage {
 pub enum PipelineMessage {
     GenerateImage,
     SetReference,
+    Pointer(PointerState),
+    RenderedFrame(Mat),
+    //intermediate stages are only collected while an operator window shows them
+    CollectStages(bool),
+    SetSubtractor(SubtractorType),
+    //name and new value of a parameter of the active subtractor
+    SetParameter(String, f64),
+    //starts the threshold calibration of the active subtractor or moves it to the next step
+    Calibrate,
+    //writes the model of the active subtractor to the model directory
+    SaveModel,
     Quit,
 }
 
@@ -23,5 +36,12 @@ pub struct CameraResult {
 pub struct BackgroundResult {
     pub mask: Result<Mat>,
     pub image: Result<Mat>,
+    pub stages: Vec<(String, Mat)>,
+    pub channels: MaskChannels,
+    pub subtractor: SubtractorType,
+    pub parameters: Vec<SubtractorParameter>,
+    //number of SetParameter messages handled so far, so the window knows if the
+    //parameters already contain its latest changes
+    pub parameter_changes: u64,
     pub timestamp: SystemTime,
 }